@@ -1,12 +1,17 @@
 use reqwest::blocking::Client;
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use reqwest::Proxy;
+use reqwest::redirect::Policy;
+use reqwest::{Method, Proxy};
 use scraper::{Html, Selector};
 use std::collections::HashMap;
 
+// (status, headers, body) of a forwarded request
+pub type Forwarded = (u16, Vec<(String, String)>, Vec<u8>);
+
 #[derive(Clone)]
 pub struct BigNet {
     client: Option<Client>,
+    forward_client: Option<Client>, // Sbig proxy routes: no redirects, no script defaults
     proxy: Option<String>,
     user_agent: Option<String>,
     headers: HashMap<String, String>,
@@ -16,6 +21,7 @@ impl BigNet {
     pub fn new() -> Self {
         BigNet {
             client: None,
+            forward_client: None,
             proxy: None,
             user_agent: None,
             headers: HashMap::new(),
@@ -119,6 +125,50 @@ impl BigNet {
         }
     }
 
+    // Sends a request on behalf of an Sbig client and hands back (status, headers, body).
+    // Redirects are passed through to the caller instead of being followed.
+    pub fn forward(
+        &mut self,
+        method: &str,
+        url: &str,
+        headers: &[(String, String)],
+        body: Vec<u8>,
+    ) -> Result<Forwarded, String> {
+        if self.forward_client.is_none() {
+            match Client::builder()
+                .redirect(Policy::none())
+                .timeout(std::time::Duration::from_secs(30))
+                .build()
+            {
+                Ok(c) => self.forward_client = Some(c),
+                Err(e) => return Err(format!("BigNet Error: Failed to build client. {}", e)),
+            }
+        }
+        let client = self.forward_client.as_ref().unwrap();
+
+        let method = Method::from_bytes(method.as_bytes())
+            .map_err(|_| format!("BigNet Error: Bad Method '{}'", method))?;
+        let mut req = client.request(method, url);
+        for (k, v) in headers {
+            req = req.header(k.as_str(), v.as_str());
+        }
+
+        let resp = req
+            .body(body)
+            .send()
+            .map_err(|e| format!("BigNet Error: Request Failed. {}", e))?;
+        let status = resp.status().as_u16();
+        let resp_headers = resp
+            .headers()
+            .iter()
+            .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.to_string(), v.to_string())))
+            .collect();
+        let data = resp
+            .bytes()
+            .map_err(|e| format!("BigNet Error: Bad Response Body. {}", e))?;
+        Ok((status, resp_headers, data.to_vec()))
+    }
+
     pub fn look_for(&self, pattern: &str, html: &str) -> String {
         let document = Html::parse_document(html);
         if let Ok(selector) = Selector::parse(pattern) {
@@ -1,3 +1,4 @@
+use crate::interpreter::bit::{sign_bytes, verify_bytes};
 use std::collections::HashMap;
 use std::fs::{self, File};
 use std::io::{Read, Seek, SeekFrom, Write};
@@ -129,6 +130,43 @@ impl BigPack {
         println!("BigPack: Done.");
     }
 
+    // Detached Ed25519 signature over the whole archive, written next to it as '<archive>.sig'.
+    pub fn sign(archive_file: &str, key_file: &str) -> Result<String, String> {
+        let key = fs::read_to_string(key_file).map_err(|e| format!("Could not read key '{}'. {}", key_file, e))?;
+        let data = fs::read(archive_file).map_err(|e| format!("Could not read '{}'. {}", archive_file, e))?;
+        let sig_file = format!("{}.sig", archive_file);
+        fs::write(&sig_file, sign_bytes(&key, &data)?).map_err(|e| format!("Could not write '{}'. {}", sig_file, e))?;
+        Ok(sig_file)
+    }
+
+    pub fn verify(archive_file: &str, public_key_file: &str) -> Result<(), String> {
+        let key = fs::read_to_string(public_key_file)
+            .map_err(|e| format!("Could not read key '{}'. {}", public_key_file, e))?;
+        let sig_file = format!("{}.sig", archive_file);
+        let sig = fs::read_to_string(&sig_file).map_err(|_| format!("'{}' is not signed (no '{}').", archive_file, sig_file))?;
+        let data = fs::read(archive_file).map_err(|e| format!("Could not read '{}'. {}", archive_file, e))?;
+        if verify_bytes(&key, &data, &sig)? {
+            Ok(())
+        } else {
+            Err(format!("Signature check failed for '{}'. The archive was changed or signed by another key.", archive_file))
+        }
+    }
+
+    // Runs before an archive launches. With a trusted key (--verify or BIGC_TRUSTED_KEY) the archive
+    // must match its '<archive>.sig'. A '.sig' with no trusted key is refused as well, since a
+    // signature is worthless without a key to check it against. Ok(false): unsigned, no key set.
+    pub fn check_launch(archive_file: &str, public_key_file: Option<&str>) -> Result<bool, String> {
+        let sig_file = format!("{}.sig", archive_file);
+        match public_key_file {
+            Some(key) => Self::verify(archive_file, key).map(|_| true),
+            None if Path::new(&sig_file).exists() => Err(format!(
+                "'{}' is signed but no trusted key is set. Run it with --verify <public.pub> or set BIGC_TRUSTED_KEY.",
+                archive_file
+            )),
+            None => Ok(false),
+        }
+    }
+
     pub fn fetch(archive_file: &str, target_file: &str) -> Option<Vec<u8>> {
         let key = DEFAULT_KEY;
 
@@ -1,5 +1,8 @@
+use super::Interpreter;
 use crate::lexer::Lexer;
 use crate::tokens::{Token, TokenType};
+use std::collections::HashMap;
+use std::path::Path;
 
 pub struct Biew;
 
@@ -536,3 +539,222 @@ impl Biew {
         }
     }
 }
+
+// --- TEMPLATES ---
+// Expanded before transpiling, line by line:
+//   {{Name}} / {{Obj.prop}}      escaped value      {{raw Name}}   value as is (inside a "..." string
+//                                                                  only its quotes/backslashes/newlines are escaped)
+//   if {X} > 1 ... or ... end if                     (BigC condition, 'or' is the else branch)
+//   loop on {List} as {Item} ... end loop            (maps: loop on {Map} as {Key} {Value})
+//   include "partial.biew"                            (relative to the including file)
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+impl Interpreter {
+    pub fn render_biew_template(&mut self, content: &str, dir: &Path) -> String {
+        let lines: Vec<&str> = content.lines().collect();
+        self.expand_biew_lines(&lines, dir, 0)
+    }
+
+    // Template expansion + transpiling of a .biew file, with includes relative to the file.
+    pub fn render_biew_file(&mut self, filename: &str, content: &str) -> String {
+        let dir = Path::new(filename).parent().unwrap_or(Path::new(".")).to_path_buf();
+        let expanded = self.render_biew_template(content, &dir);
+        Biew::transpile_biew(&expanded)
+    }
+
+    fn expand_biew_lines(&mut self, lines: &[&str], dir: &Path, depth: usize) -> String {
+        let mut out = String::new();
+        let mut idx = 0;
+        while idx < lines.len() {
+            let line = lines[idx];
+            let trimmed = line.trim();
+            let lower = trimmed.to_lowercase();
+
+            if lower.starts_with("if ") {
+                let (end, split) = find_block_end(lines, idx, "end if");
+                let cond_tokens: Vec<Token> = Lexer::new(&trimmed[3..])
+                    .tokenize()
+                    .into_iter()
+                    .filter(|t| t.token_type != TokenType::EOF)
+                    .collect();
+                let branch = if self.evaluate_condition(&cond_tokens) {
+                    &lines[idx + 1..split.unwrap_or(end)]
+                } else if let Some(or_idx) = split {
+                    &lines[or_idx + 1..end]
+                } else {
+                    &lines[end..end]
+                };
+                out.push_str(&self.expand_biew_lines(branch, dir, depth));
+                idx = end + 1;
+                continue;
+            }
+
+            if lower.starts_with("loop on ") {
+                let (end, _) = find_block_end(lines, idx, "end loop");
+                let names = brace_names(trimmed);
+                let body = &lines[idx + 1..end.min(lines.len())];
+                if names.len() >= 2 {
+                    let source = self.get_variable(&names[0]).unwrap_or_default();
+                    let items: Vec<(String, String)> =
+                        match serde_json::from_str::<serde_json::Value>(&source) {
+                            Ok(serde_json::Value::Object(map)) => map
+                                .into_iter()
+                                .map(|(k, v)| (k, plain_json(&v)))
+                                .collect(),
+                            Ok(serde_json::Value::Array(list)) => list
+                                .iter()
+                                .map(|v| (plain_json(v), String::new()))
+                                .collect(),
+                            _ => Vec::new(),
+                        };
+                    for (first, second) in items {
+                        let mut scope = HashMap::new();
+                        scope.insert(names[1].clone(), first);
+                        if let Some(val_name) = names.get(2) {
+                            scope.insert(val_name.clone(), second);
+                        }
+                        self.local_scopes.push(scope);
+                        let rendered = self.expand_biew_lines(body, dir, depth);
+                        self.local_scopes.pop();
+                        out.push_str(&rendered);
+                    }
+                }
+                idx = end + 1;
+                continue;
+            }
+
+            if lower.starts_with("include ") {
+                let name = trimmed[8..].trim().trim_matches('"');
+                let path = dir.join(name);
+                if depth >= MAX_INCLUDE_DEPTH {
+                    println!("Big Error: Biew include depth exceeded at '{}'", name);
+                } else if let Ok(partial) = std::fs::read_to_string(&path) {
+                    let partial_dir = path.parent().unwrap_or(dir).to_path_buf();
+                    let partial_lines: Vec<&str> = partial.lines().collect();
+                    out.push_str(&self.expand_biew_lines(&partial_lines, &partial_dir, depth + 1));
+                } else {
+                    println!("Big Error: Biew include '{}' not found", path.display());
+                }
+                idx += 1;
+                continue;
+            }
+
+            out.push_str(&self.fill_biew_placeholders(line));
+            out.push('\n');
+            idx += 1;
+        }
+        out
+    }
+
+    fn fill_biew_placeholders(&self, line: &str) -> String {
+        let mut out = String::new();
+        let mut rest = line;
+        while let Some(start) = rest.find("{{") {
+            let Some(len) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            out.push_str(&rest[..start]);
+            let inner = rest[start + 2..start + 2 + len].trim();
+            match inner.strip_prefix("raw ") {
+                Some(name) => {
+                    let value = self.get_variable(name.trim()).unwrap_or_default();
+                    if inside_biew_string(&out) {
+                        out.push_str(&escape_biew_string(&value));
+                    } else {
+                        out.push_str(&value);
+                    }
+                }
+                None => out.push_str(&escape_html(&self.get_variable(inner).unwrap_or_default())),
+            }
+            rest = &rest[start + 2 + len + 2..];
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+// Returns (index of the closing line, index of a top-level 'or' line).
+// A missing closer runs to the end of the block.
+fn find_block_end(lines: &[&str], start: usize, closer: &str) -> (usize, Option<usize>) {
+    let mut nesting: usize = 0;
+    let mut split = None;
+    for (idx, line) in lines.iter().enumerate().skip(start + 1) {
+        let lower = line.trim().to_lowercase();
+        if lower.starts_with("if ") || lower.starts_with("loop on ") {
+            nesting += 1;
+        } else if lower == "end if" || lower == "end loop" {
+            if nesting == 0 && lower == closer {
+                return (idx, split);
+            }
+            nesting = nesting.saturating_sub(1);
+        } else if lower == "or" && nesting == 0 && split.is_none() {
+            split = Some(idx);
+        }
+    }
+    (lines.len(), split)
+}
+
+fn brace_names(text: &str) -> Vec<String> {
+    text.split('{')
+        .skip(1)
+        .filter_map(|part| part.split_once('}').map(|(name, _)| name.trim().to_string()))
+        .collect()
+}
+
+// Strings lose their JSON quotes, everything else stays JSON (nested maps/lists).
+fn plain_json(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+// True when the line so far has an unclosed "..." string, so a placeholder lands inside it.
+fn inside_biew_string(line: &str) -> bool {
+    let mut inside = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if inside => {
+                chars.next();
+            }
+            '"' => inside = !inside,
+            _ => {}
+        }
+    }
+    inside
+}
+
+// Lexer escapes, so a raw value can't close the string or split the Biew line.
+fn escape_biew_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// Escapes for HTML and keeps the value on one Biew line inside a "..." string.
+fn escape_html(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            '\\' => out.push_str("&#92;"),
+            '\n' => out.push_str("&#10;"),
+            '\r' => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
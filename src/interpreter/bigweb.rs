@@ -1,7 +1,145 @@
 use super::Interpreter;
 use crate::tokens::{Token, TokenType};
+use crate::lexer::Lexer;
+use base64::{engine::general_purpose, Engine as _};
+use percent_encoding::percent_decode_str;
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use tiny_http::{Header, Response, Server};
+use std::io::{Cursor, Read, Write};
+use std::net::TcpListener;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+use tiny_http::{Header, ReadWrite, Request, Response, Server};
+
+// --- RATE LIMITING (Token Bucket) ---
+// One rule per route prefix ("" covers every route). Each client key gets its own bucket.
+#[derive(Debug, Clone)]
+pub struct RateRule {
+    pub prefix: String,
+    pub rate: f64,  // Tokens refilled per second
+    pub burst: f64, // Bucket capacity
+}
+
+#[derive(Debug, Clone)]
+pub struct RateBucket {
+    pub tokens: f64,
+    pub last: Instant,
+}
+
+// --- METRICS (Prometheus) ---
+// Upper bounds (seconds) of the latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct RouteMetrics {
+    pub statuses: HashMap<u16, u64>,
+    pub buckets: [u64; 11],
+    pub sum: f64,
+    pub count: u64,
+}
+
+// --- CORS ---
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    pub origins: Vec<String>, // "*" allows any origin
+    pub methods: String,
+    pub headers: String, // Empty: echo what the preflight asks for
+    pub credentials: bool,
+    pub max_age: u64,
+}
+
+// --- OPENAPI ---
+#[derive(Debug, Clone, Default)]
+pub struct RouteDoc {
+    pub summary: String,
+    pub params: Vec<(String, String, String)>, // (name, path/query/header, type)
+    pub responses: Vec<(u16, String, String)>, // (status, description, schema)
+}
+
+// Never copied between the client and a proxied upstream (RFC 9110 7.6.1)
+const HOP_HEADERS: [&str; 10] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+    "content-length",
+];
+
+// --- REQUEST DISPATCH ---
+// A request with its body already read, detached from the socket it came from.
+pub struct SbigRequest {
+    pub method: String,
+    pub url: String,
+    pub client: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    pub form: Option<MultipartForm>, // Multipart body parsed (files saved) while it was read
+}
+
+// Form fields + saved file entries of a multipart/form-data body.
+pub type MultipartForm = (serde_json::Map<String, serde_json::Value>, Vec<serde_json::Value>);
+
+impl SbigRequest {
+    pub fn header(&self, name: &str) -> Option<String> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.clone())
+    }
+}
+
+pub enum Dispatch {
+    Reply(Response<Cursor<Vec<u8>>>, Option<String>), // Response + metrics route
+    Stream(String, String),                           // Path + doing
+    Socket(String, String),
+}
+
+// Where a request is headed, decided before its body is read.
+enum Routed {
+    Done(Dispatch),                        // Answered without the body (metrics, preflight, 429, live)
+    Proxy(String, String),                 // Prefix + upstream
+    Doing(Option<String>, String, String), // Doing (None if unmatched) + metrics route + RequestExtra
+}
+
+#[derive(Debug, Clone)]
+pub struct SimResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+// --- LIVE CLIENTS (SSE + WebSocket) ---
+// Every live client has its own thread; pushes only queue a message for it.
+// Stream writers send a heartbeat comment when idle, so a closed client is noticed within SSE_HEARTBEAT.
+// Socket threads ping before every read (see serve_socket), and the listener's TCP user timeout
+// drops a client that stops acknowledging them.
+pub enum LiveSink {
+    Stream(Sender<String>),
+    Socket(Sender<WsFrame>),
+}
+
+type WsFrame = (u8, Vec<u8>); // (opcode, payload)
+
+pub struct LiveClient {
+    pub route: String,
+    pub sink: LiveSink,
+    pub channels: HashSet<String>,
+}
+
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const WS_MAX_MESSAGE: usize = 16 * 1024 * 1024;
+const SSE_HEARTBEAT: Duration = Duration::from_secs(15);
+const SOCKET_POLL: Duration = Duration::from_millis(100);
+#[cfg(target_os = "linux")]
+const TCP_USER_TIMEOUT: Duration = Duration::from_secs(30);
 
 impl Interpreter {
     pub fn handle_use_sbig(&mut self, i: &mut usize, tokens: &Vec<Token>) {
@@ -39,15 +177,61 @@ impl Interpreter {
                 }
                 TokenType::Limit => {
                     *i += 1;
+                    if *i < tokens.len() && tokens[*i].token_type == TokenType::By {
+                        // control limit by run PickKey
+                        *i += 1;
+                        if *i + 1 < tokens.len() && tokens[*i].token_type == TokenType::Run {
+                            *i += 1;
+                            self.rate_key_doing = Some(self.get_token_raw_name(&tokens[*i]));
+                        }
+                        return;
+                    }
+
                     let val = self.get_token_value(&tokens[*i]);
-                    self.rate_limit = val.parse::<usize>().unwrap_or(0);
-                    // Consume "per minute" if present
+                    let count = val.parse::<f64>().unwrap_or(0.0);
+                    let mut window_secs = 60.0;
+                    let mut burst = count;
+                    let mut prefix = String::new();
+
+                    // Consume "per mins" if present
                     if *i + 1 < tokens.len() && tokens[*i + 1].token_type == TokenType::Per {
                         *i += 2;
-                        if *i < tokens.len() && tokens[*i].token_type == TokenType::Mins {
-                            // Valid syntax confirmed
+                        if *i < tokens.len() {
+                            window_secs = match &tokens[*i].token_type {
+                                TokenType::Mins => 60.0,
+                                TokenType::Identifier(unit) => match unit.to_lowercase().as_str() {
+                                    "sec" | "secs" | "second" | "seconds" => 1.0,
+                                    "hour" | "hours" => 3600.0,
+                                    _ => 60.0,
+                                },
+                                _ => 60.0,
+                            };
                         }
                     }
+
+                    // Optional: burst N
+                    if *i + 2 < tokens.len()
+                        && tokens[*i + 1].token_type == TokenType::Identifier(String::from("burst"))
+                    {
+                        *i += 2;
+                        burst = self.get_token_value(&tokens[*i]).parse::<f64>().unwrap_or(count);
+                    }
+
+                    // Optional: on "/api" (Route prefix)
+                    if *i + 2 < tokens.len() && tokens[*i + 1].token_type == TokenType::On {
+                        *i += 2;
+                        let prefix_raw = self.get_token_value(&tokens[*i]);
+                        prefix = self.interpolate_string(&prefix_raw);
+                    }
+
+                    self.rate_rules.retain(|r| r.prefix != prefix);
+                    if count > 0.0 {
+                        self.rate_rules.push(RateRule {
+                            prefix,
+                            rate: count / window_secs,
+                            burst: burst.max(1.0),
+                        });
+                    }
                 }
                 TokenType::Record => {
                     *i += 1;
@@ -58,6 +242,117 @@ impl Interpreter {
                         *i += 1;
                         let filename = self.get_token_value(&tokens[*i]);
                         self.log_file = Some(filename);
+
+                        // Optional: as clf / as json
+                        if *i + 1 < tokens.len() && tokens[*i + 1].token_type == TokenType::As {
+                            *i += 2;
+                            if *i < tokens.len() {
+                                self.log_format = self.get_token_raw_name(&tokens[*i]).to_lowercase();
+                            }
+                        }
+                    }
+                }
+                TokenType::Identifier(ref s) if s == "metrics" => {
+                    // control metrics @"/metrics"
+                    *i += 1;
+                    if *i < tokens.len() && tokens[*i].token_type == TokenType::At {
+                        if !self.validate_at_strictness(*i, tokens) {
+                            return;
+                        }
+                        *i += 1;
+                        let path = self.get_token_value(&tokens[*i]);
+                        self.metrics_path = Some(path);
+                    }
+                }
+                TokenType::Identifier(ref s) if s == "openapi" => {
+                    // control openapi @"/openapi.json"
+                    *i += 1;
+                    if *i < tokens.len() && tokens[*i].token_type == TokenType::At {
+                        if !self.validate_at_strictness(*i, tokens) {
+                            return;
+                        }
+                        *i += 1;
+                        let path = self.get_token_value(&tokens[*i]);
+                        self.openapi_path = Some(path);
+                    }
+                }
+                TokenType::Body => {
+                    // control body 10 mb
+                    *i += 1;
+                    if *i < tokens.len() {
+                        let size = self.get_token_value(&tokens[*i]).parse::<f64>().unwrap_or(0.0);
+                        let mut factor = 1.0;
+                        if *i + 1 < tokens.len() {
+                            if let TokenType::Identifier(ref unit) = tokens[*i + 1].token_type {
+                                let unit_factor = match unit.to_lowercase().as_str() {
+                                    "kb" => Some(1024.0),
+                                    "mb" => Some(1024.0 * 1024.0),
+                                    "gb" => Some(1024.0 * 1024.0 * 1024.0),
+                                    _ => None,
+                                };
+                                // Anything else is left for the next statement (plain bytes)
+                                if let Some(f) = unit_factor {
+                                    factor = f;
+                                    *i += 1;
+                                }
+                            }
+                        }
+                        self.max_body = (size * factor) as usize;
+                    }
+                }
+                TokenType::Identifier(ref s) if s == "uploads" => {
+                    // control uploads @"uploads/"
+                    *i += 1;
+                    if *i < tokens.len() && tokens[*i].token_type == TokenType::At {
+                        if !self.validate_at_strictness(*i, tokens) {
+                            return;
+                        }
+                        *i += 1;
+                        let dir = self.get_token_value(&tokens[*i]);
+                        self.upload_dir = Some(dir);
+                    }
+                }
+                TokenType::Identifier(ref s) if s == "cors" => {
+                    // control cors "https://app.example.com, https://admin.example.com"
+                    // control cors methods "GET, POST" / headers "Content-Type" / credentials / age 600
+                    *i += 1;
+                    if *i >= tokens.len() {
+                        return;
+                    }
+                    let policy = self.cors.get_or_insert_with(|| CorsPolicy {
+                        origins: Vec::new(),
+                        methods: String::from("GET, POST, PUT, PATCH, DELETE, OPTIONS"),
+                        headers: String::new(),
+                        credentials: false,
+                        max_age: 600,
+                    });
+                    let setting = match tokens[*i].token_type {
+                        TokenType::Identifier(ref w) => w.to_lowercase(),
+                        _ => String::new(),
+                    };
+                    match setting.as_str() {
+                        "credentials" => policy.credentials = true,
+                        "methods" | "headers" | "age" if *i + 1 < tokens.len() => {
+                            *i += 1;
+                            let raw = self.get_token_value(&tokens[*i]);
+                            let val = self.interpolate_string(&raw);
+                            let policy = self.cors.as_mut().unwrap();
+                            match setting.as_str() {
+                                "methods" => policy.methods = val.to_uppercase(),
+                                "headers" => policy.headers = val,
+                                _ => policy.max_age = val.parse::<u64>().unwrap_or(600),
+                            }
+                        }
+                        _ => {
+                            let raw = self.get_token_value(&tokens[*i]);
+                            let val = self.interpolate_string(&raw);
+                            let policy = self.cors.as_mut().unwrap();
+                            policy.origins = val
+                                .split(',')
+                                .map(|o| o.trim().trim_end_matches('/').to_string())
+                                .filter(|o| !o.is_empty())
+                                .collect();
+                        }
                     }
                 }
                 TokenType::SSL => {
@@ -89,27 +384,285 @@ impl Interpreter {
         }
         *i += 1;
         if *i < tokens.len() {
+            // Live routes share the table: STREAM/SOCKET/OPEN/CLOSE keys
             let method = match tokens[*i].token_type {
                 TokenType::Get => "GET",
                 TokenType::Post => "POST",
+                TokenType::Open => "OPEN",
+                TokenType::Close => "CLOSE",
+                TokenType::Identifier(ref s) if s == "stream" => "STREAM",
+                TokenType::Identifier(ref s) if s == "socket" => "SOCKET",
+                TokenType::Proxy => "PROXY",
                 _ => "GET",
             };
             *i += 1;
             if *i < tokens.len() {
                 let path = self.get_token_value(&tokens[*i]);
                 *i += 1;
+                // on proxy "/users" to "http://127.0.0.1:9001"
+                if method == "PROXY" && *i + 1 < tokens.len() && tokens[*i].token_type == TokenType::To {
+                    *i += 1;
+                    let upstream_raw = self.get_token_value(&tokens[*i]);
+                    let upstream = self.interpolate_string(&upstream_raw);
+                    let prefix = path.trim_end_matches('/').to_string();
+                    if let Ok(mut r) = self.routes.write() {
+                        r.insert(format!("PROXY {}", prefix), upstream);
+                    }
+                    self.last_route = None;
+                    return;
+                }
                 if *i < tokens.len() && tokens[*i].token_type == TokenType::Run {
                     *i += 1;
                     if *i < tokens.len() {
                         let doing = self.get_token_value(&tokens[*i]);
                         let key = format!("{} {}", method, path);
                         if let Ok(mut r) = self.routes.write() {
-                            r.insert(key, doing);
+                            r.insert(key.clone(), doing);
                         }
+                        self.last_route = Some(key);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn handle_api(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        // Syntax (Describes the route declared just above):
+        // api summary "List users"
+        // api param "id" in path as "integer"   (path / query / header)
+        // api returns 200 "The user" as "object"  (Type name or JSON schema)
+        // api title "Shop API" version "2.1"
+        *i += 1; // Skip "api"
+        if *i + 1 >= tokens.len() {
+            return;
+        }
+
+        if tokens[*i].token_type == TokenType::Title {
+            *i += 1;
+            self.api_info.0 = self.get_token_value(&tokens[*i]);
+            if *i + 2 < tokens.len() && tokens[*i + 1].token_type == TokenType::Identifier(String::from("version")) {
+                *i += 2;
+                self.api_info.1 = self.get_token_value(&tokens[*i]);
+            }
+            return;
+        }
+
+        let word = self.get_token_raw_name(&tokens[*i]).to_lowercase();
+        let Some(route) = self.last_route.clone() else {
+            println!("Big Error: 'api {}' must follow an 'on' route.", word);
+            return;
+        };
+        *i += 1;
+
+        match word.as_str() {
+            "summary" => {
+                let text = self.get_token_value(&tokens[*i]);
+                self.route_docs.entry(route).or_default().summary = text;
+            }
+            "param" => {
+                let name = self.get_token_value(&tokens[*i]);
+                let mut location = String::from("query");
+                let mut kind = String::from("string");
+                if *i + 2 < tokens.len() && tokens[*i + 1].token_type == TokenType::In {
+                    *i += 2;
+                    location = match tokens[*i].token_type {
+                        TokenType::Path => String::from("path"),
+                        TokenType::Header => String::from("header"),
+                        _ => self.get_token_raw_name(&tokens[*i]).to_lowercase(),
+                    };
+                }
+                if *i + 2 < tokens.len() && tokens[*i + 1].token_type == TokenType::As {
+                    *i += 2;
+                    kind = self.get_token_value(&tokens[*i]);
+                }
+                if !["path", "query", "header"].contains(&location.as_str()) {
+                    println!("Big Error: 'api param' location must be path, query or header.");
+                    return;
+                }
+                self.route_docs
+                    .entry(route)
+                    .or_default()
+                    .params
+                    .push((name, location, kind));
+            }
+            "returns" => {
+                let status = self.get_token_value(&tokens[*i]).parse::<u16>().unwrap_or(200);
+                let mut description = String::new();
+                let mut schema = String::new();
+                if *i + 1 < tokens.len() {
+                    if let TokenType::String(ref text) = tokens[*i + 1].token_type {
+                        description = text.clone();
+                        *i += 1;
+                    }
+                }
+                if *i + 2 < tokens.len() && tokens[*i + 1].token_type == TokenType::As {
+                    *i += 2;
+                    schema = self.get_token_value(&tokens[*i]);
+                }
+                self.route_docs
+                    .entry(route)
+                    .or_default()
+                    .responses
+                    .push((status, description, schema));
+            }
+            _ => println!("Big Error: Unknown 'api {}'. Use summary, param, returns or title.", word),
+        }
+    }
+
+    // Every registered route as (method, path, target), sorted by path.
+    // 'bigrun routes' only needs the route table, so only 'use web', 'on' and 'api' lines
+    // (and the files pulled in with 'use "file.big"') are replayed. Nothing else in the script runs.
+    pub fn collect_routes(&mut self, tokens: &[Token], depth: usize) {
+        let mut idx = 0;
+        while idx < tokens.len() {
+            let line = tokens[idx].line;
+            let end = tokens[idx..]
+                .iter()
+                .position(|t| t.line != line || t.token_type == TokenType::EOF)
+                .map_or(tokens.len(), |p| idx + p);
+            let statement = &tokens[idx..end.max(idx + 1)];
+            idx = end.max(idx + 1);
+
+            let next = statement.get(1).map(|t| &t.token_type);
+            match (&statement[0].token_type, next) {
+                (TokenType::Use, Some(TokenType::Sbig)) => self.sbig_enabled = true,
+                (TokenType::Use, Some(TokenType::Identifier(w))) if w.eq_ignore_ascii_case("web") => {
+                    self.sbig_enabled = true;
+                }
+                (TokenType::On, _) => self.run(statement.to_vec()),
+                (TokenType::Identifier(w), _) if w.eq_ignore_ascii_case("api") => {
+                    self.run(statement.to_vec());
+                }
+                (TokenType::Use, Some(TokenType::String(filename))) => {
+                    if depth >= 16 {
+                        println!("Big Error: 'use' nesting too deep at '{}'", filename);
+                    } else if let Some(content) = self.resolve_file_as_string(filename) {
+                        let used = Lexer::new(&content).tokenize();
+                        self.collect_routes(&used, depth + 1);
                     }
                 }
+                _ => {}
+            }
+        }
+    }
+
+    fn route_table(&self) -> Vec<(String, String, String)> {
+        let mut table: Vec<(String, String, String)> = if let Ok(r) = self.routes.read() {
+            r.iter()
+                .filter_map(|(key, target)| {
+                    let (method, path) = key.split_once(' ')?;
+                    Some((method.to_string(), path.to_string(), target.clone()))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        table.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+        table
+    }
+
+    pub fn print_routes(&self) {
+        let table = self.route_table();
+        if table.is_empty() {
+            println!("BigWeb: No routes registered.");
+            return;
+        }
+        let width = table.iter().map(|(_, p, _)| p.len()).max().unwrap_or(0);
+        for (method, path, target) in table {
+            let key = format!("{} {}", method, path);
+            let summary = self
+                .route_docs
+                .get(&key)
+                .map(|d| d.summary.clone())
+                .unwrap_or_default();
+            let arrow = if method == "PROXY" { "~>" } else { "->" };
+            println!("{:<7} {:<width$}  {} {:<16} {}", method, path, arrow, target, summary, width = width);
+        }
+    }
+
+    // OpenAPI 3 document for the GET/POST routes. Live and proxy routes have no fixed shape and are left out.
+    pub fn render_openapi(&self) -> String {
+        let mut paths = serde_json::Map::new();
+        for (method, path, doing) in self.route_table() {
+            if method != "GET" && method != "POST" {
+                continue;
+            }
+            let doc = self
+                .route_docs
+                .get(&format!("{} {}", method, path))
+                .cloned()
+                .unwrap_or_default();
+
+            let mut params = doc.params.clone();
+            // Wildcard tail ('+') is $RequestExtra, named after the first path param if there is one
+            let api_path = match path.strip_suffix('+') {
+                Some(base) => {
+                    let name = match params.iter().find(|(_, loc, _)| loc == "path") {
+                        Some((name, _, _)) => name.clone(),
+                        None => {
+                            params.push((String::from("extra"), String::from("path"), String::from("string")));
+                            String::from("extra")
+                        }
+                    };
+                    format!("{}{{{}}}", base, name)
+                }
+                None => path.clone(),
+            };
+
+            let parameters: Vec<serde_json::Value> = params
+                .iter()
+                .map(|(name, location, kind)| {
+                    serde_json::json!({
+                        "name": name,
+                        "in": location,
+                        "required": location == "path",
+                        "schema": schema_value(kind),
+                    })
+                })
+                .collect();
+
+            let mut responses = serde_json::Map::new();
+            for (status, description, schema) in &doc.responses {
+                let description = if description.is_empty() {
+                    String::from("Response")
+                } else {
+                    description.clone()
+                };
+                let mut entry = serde_json::json!({ "description": description });
+                if !schema.is_empty() {
+                    entry["content"] = serde_json::json!({
+                        "application/json": { "schema": schema_value(schema) }
+                    });
+                }
+                responses.insert(status.to_string(), entry);
+            }
+            if responses.is_empty() {
+                responses.insert(String::from("200"), serde_json::json!({ "description": "OK" }));
+            }
+
+            let mut operation = serde_json::json!({
+                "operationId": doing,
+                "responses": responses,
+            });
+            if !doc.summary.is_empty() {
+                operation["summary"] = serde_json::Value::String(doc.summary.clone());
+            }
+            if !parameters.is_empty() {
+                operation["parameters"] = serde_json::Value::Array(parameters);
             }
+
+            let item = paths
+                .entry(api_path)
+                .or_insert_with(|| serde_json::json!({}));
+            item[method.to_lowercase()] = operation;
         }
+
+        let spec = serde_json::json!({
+            "openapi": "3.0.3",
+            "info": { "title": self.api_info.0, "version": self.api_info.1 },
+            "paths": paths,
+        });
+        serde_json::to_string_pretty(&spec).unwrap_or_default()
     }
 
     pub fn handle_start_server(&mut self, i: &mut usize, tokens: &Vec<Token>) {
@@ -126,173 +679,1162 @@ impl Interpreter {
 
             let port = self.get_token_value(&tokens[*i]);
             let addr = format!("127.0.0.1:{}", port);
+            if self.sbig_simulate {
+                println!("BigWeb: Simulation mode, routes loaded without binding {}", addr);
+                return;
+            }
             println!("BigWeb: Listening on http://{}", addr);
 
+            let listener = bind_listener(&addr).unwrap();
             let server = if let Some((ref _cert, ref _key)) = self.ssl_config {
                 println!("BigWeb: SSL Shield Enabled.");
                 // Note: Standard tiny-http doesn't have easy one-liner SSL without features.
                 // We will attempt to open it, but standard builds might fallback to HTTP.
-                Server::http(&addr).unwrap()
+                Server::from_listener(listener, None).unwrap()
             } else {
-                Server::http(&addr).unwrap()
+                Server::from_listener(listener, None).unwrap()
             };
 
-            let mut request_count = 0;
-            let mut last_minute = std::time::SystemTime::now();
-
-            for mut request in server.incoming_requests() {
+            for request in server.incoming_requests() {
                 println!("BigWeb DEBUG: {} {}", request.method(), request.url());
-                // Logging
-                if let Some(ref log_path) = self.log_file {
-                    use std::io::Write;
-                    if let Ok(mut f) = std::fs::OpenOptions::new()
-                        .append(true)
-                        .create(true)
-                        .open(log_path)
-                    {
-                        let _ = writeln!(
-                            f,
-                            "[{}] {} {}",
-                            chrono::Local::now(),
-                            request.method(),
-                            request.url()
-                        );
-                    }
-                }
-
-                // Rate Limiting Logic
-                if self.rate_limit > 0 {
-                    if let Ok(elapsed) = last_minute.elapsed() {
-                        if elapsed.as_secs() >= 60 {
-                            request_count = 0;
-                            last_minute = std::time::SystemTime::now();
+                self.handle_request(request);
+            }
+        }
+    }
+
+    // A real connection: routes on the head, reads the capped body if the route needs it,
+    // then answers or goes live.
+    fn handle_request(&mut self, mut request: Request) {
+        let started = Instant::now();
+        let mut req = SbigRequest {
+            method: request.method().as_str().to_uppercase(),
+            url: request.url().to_string(),
+            client: request
+                .remote_addr()
+                .map(|a| a.ip().to_string())
+                .unwrap_or_default(),
+            headers: request
+                .headers()
+                .iter()
+                .map(|h| (h.field.as_str().as_str().to_string(), h.value.as_str().to_string()))
+                .collect(),
+            body: Vec::new(),
+            form: None,
+        };
+
+        let routed = self.route_request(&req);
+
+        // Only a proxy or a matched doing reads the body, so refused and unmatched requests
+        // never save uploads. Upgrade requests hand the raw socket over as their body.
+        let upgrading = req
+            .header("Connection")
+            .is_some_and(|v| v.to_lowercase().contains("upgrade"));
+        let needs_body = matches!(routed, Routed::Proxy(..) | Routed::Doing(Some(_), ..));
+        if needs_body && !upgrading {
+            // Proxies forward the raw bytes; a doing gets its file parts streamed to disk
+            let boundary = match routed {
+                Routed::Doing(..) => req
+                    .header("Content-Type")
+                    .and_then(|ct| multipart_boundary(&ct)),
+                _ => None,
+            };
+            let too_large = request.body_length().unwrap_or(0) > self.max_body || {
+                let mut body = CappedReader {
+                    inner: request.as_reader(),
+                    left: self.max_body,
+                    exceeded: false,
+                };
+                match boundary {
+                    // File parts go to disk as they arrive instead of through memory
+                    Some(boundary) => {
+                        let form = parse_multipart(&mut body, &boundary, &self.upload_path());
+                        if body.exceeded {
+                            remove_uploads(&form.1);
+                        } else {
+                            req.form = Some(form);
                         }
                     }
-
-                    if request_count >= self.rate_limit {
-                        let _ = request.respond(
-                            Response::from_string("429 Too Many Requests").with_status_code(429),
-                        );
-                        continue;
+                    None => {
+                        let _ = body.read_to_end(&mut req.body);
                     }
-                    request_count += 1;
                 }
+                body.exceeded
+            };
+            if too_large {
+                let response = self.with_cors(
+                    Response::from_string("413 Payload Too Large").with_status_code(413),
+                    &req,
+                );
+                self.finish_request(request, response, Some("unmatched"), started);
+                return;
+            }
+        }
+
+        match self.answer_route(&req, routed) {
+            Dispatch::Reply(response, route) => {
+                self.finish_request(request, response, route.as_deref(), started)
+            }
+            Dispatch::Stream(path, doing) => self.open_stream(request, path, doing),
+            Dispatch::Socket(path, doing) => self.open_socket(request, path, doing),
+        }
+    }
+
+    // Runs a request through Sbig without a socket (see 'simulate').
+    // Live routes can't be simulated and answer 501.
+    pub fn simulate_request(
+        &mut self,
+        method: &str,
+        url: &str,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    ) -> SimResponse {
+        let req = SbigRequest {
+            method: method.to_uppercase(),
+            url: url.to_string(),
+            client: String::from("127.0.0.1"),
+            headers,
+            body,
+            form: None,
+        };
+
+        let response = if req.body.len() > self.max_body {
+            self.with_cors(
+                Response::from_string("413 Payload Too Large").with_status_code(413),
+                &req,
+            )
+        } else {
+            match self.dispatch_request(&req) {
+                Dispatch::Reply(response, _) => response,
+                Dispatch::Stream(..) | Dispatch::Socket(..) => {
+                    Response::from_string("501 Live routes can't be simulated").with_status_code(501)
+                }
+            }
+        };
 
-                let method = request.method().as_str().to_uppercase();
-                let url = request.url().to_string();
+        SimResponse {
+            status: response.status_code().0,
+            headers: response
+                .headers()
+                .iter()
+                .map(|h| (h.field.as_str().as_str().to_string(), h.value.as_str().to_string()))
+                .collect(),
+            body: response.into_reader().into_inner(),
+        }
+    }
 
-                // Route Matching (Exact + Wildcard)
-                let mut matched_doing = None;
-                let mut request_extra = String::new();
+    // Everything between reading a request and writing its answer. Socket-free on purpose:
+    // the server loop and the simulator both come through here.
+    fn dispatch_request(&mut self, req: &SbigRequest) -> Dispatch {
+        let routed = self.route_request(req);
+        self.answer_route(req, routed)
+    }
 
-                let routes_snapshot = if let Ok(r) = self.routes.read() {
-                    r.clone()
-                } else {
-                    std::collections::HashMap::new()
-                };
+    // Runs the proxy or doing the request was routed to and adds the CORS headers.
+    fn answer_route(&mut self, req: &SbigRequest, routed: Routed) -> Dispatch {
+        let dispatch = match routed {
+            Routed::Done(dispatch) => dispatch,
+            Routed::Proxy(prefix, upstream) => {
+                Dispatch::Reply(self.forward_request(req, &prefix, &upstream), Some(prefix))
+            }
+            Routed::Doing(doing, route, extra) => {
+                Dispatch::Reply(self.run_route(req, doing, extra), Some(route))
+            }
+        };
+        match dispatch {
+            Dispatch::Reply(response, route) => Dispatch::Reply(self.with_cors(response, req), route),
+            live => live,
+        }
+    }
 
-                for (route_key, doing) in routes_snapshot {
-                    let parts: Vec<&str> = route_key.split_whitespace().collect();
-                    if parts.len() < 2 {
-                        continue;
-                    }
-                    let r_method = parts[0];
-                    let r_path = parts[1];
+    // Decides where a request goes from its head alone (the body may not be read yet).
+    fn route_request(&mut self, req: &SbigRequest) -> Routed {
+        let method = req.method.clone();
+        let url = req.url.clone();
+        let client = req.client.clone();
 
-                    if r_method == method {
-                        if let Some(base) = r_path.strip_suffix('+') {
-                            if url.starts_with(base) {
-                                matched_doing = Some(doing.clone());
-                                request_extra = url[base.len()..].to_string();
-                                break;
+        let mut headers_map = serde_json::Map::new();
+        for (k, v) in &req.headers {
+            headers_map.insert(k.to_lowercase(), serde_json::Value::String(v.clone()));
+        }
+
+        self.set_variable("RequestPath".to_string(), url.clone());
+        self.set_variable("RequestMethod".to_string(), method.clone());
+        self.set_variable("RequestClient".to_string(), client.clone());
+        self.set_variable(
+            "RequestHeaders".to_string(),
+            serde_json::Value::Object(headers_map).to_string(),
+        );
+
+        // Built-in Metrics Endpoint (Not rate limited, not counted)
+        if let Some(ref metrics_path) = self.metrics_path {
+            if method == "GET" && url.split('?').next().unwrap_or("") == metrics_path {
+                let response = Response::from_string(self.render_metrics()).with_header(
+                    Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..])
+                        .unwrap(),
+                );
+                return Routed::Done(Dispatch::Reply(response, None));
+            }
+        }
+
+        // Built-in OpenAPI Document
+        if let Some(ref openapi_path) = self.openapi_path {
+            if method == "GET" && url.split('?').next().unwrap_or("") == openapi_path {
+                let response = Response::from_string(self.render_openapi()).with_header(
+                    Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+                );
+                return Routed::Done(Dispatch::Reply(response, None));
+            }
+        }
+
+        // CORS Preflight (Answered before rate limiting and routing)
+        if method == "OPTIONS" && self.cors.is_some() {
+            if let (Some(origin), Some(_)) =
+                (req.header("Origin"), req.header("Access-Control-Request-Method"))
+            {
+                let requested = req.header("Access-Control-Request-Headers").unwrap_or_default();
+                let response = match self.cors_preflight_headers(&origin, &requested) {
+                    Some(headers) => {
+                        let mut response = Response::from_string("").with_status_code(204);
+                        for (k, v) in headers {
+                            if let Ok(h) = Header::from_bytes(k.as_bytes(), v.as_bytes()) {
+                                response = response.with_header(h);
                             }
-                        } else if r_path == url.split('?').next().unwrap_or("") {
-                            matched_doing = Some(doing.clone());
-                            break;
                         }
+                        response
                     }
-                }
+                    None => Response::from_string("403 Origin Not Allowed").with_status_code(403),
+                };
+                return Routed::Done(Dispatch::Reply(response, Some(String::from("preflight"))));
+            }
+        }
+
+        // Rate Limiting Logic (Per Client Token Bucket)
+        if let Some(retry_after) = self.check_rate_limit(&url, &client) {
+            let mut response = Response::from_string("429 Too Many Requests").with_status_code(429);
+            if let Ok(h) =
+                Header::from_bytes(&b"Retry-After"[..], retry_after.to_string().as_bytes())
+            {
+                response = response.with_header(h);
+            }
+            return Routed::Done(Dispatch::Reply(response, Some(String::from("rate_limited"))));
+        }
 
-                self.set_variable("Sbig_Response_Body".to_string(), String::new());
-                self.set_variable("Sbig_Response_File".to_string(), String::new());
-                self.set_variable("RequestPath".to_string(), url.clone());
-                self.set_variable("RequestMethod".to_string(), method.clone());
-                self.set_variable("RequestExtra".to_string(), request_extra);
-                self.current_status = 200;
-                self.current_headers.clear();
+        // Live Routes (SSE + WebSocket)
+        if method == "GET" {
+            let path = url.split('?').next().unwrap_or("").to_string();
+            let (stream_doing, socket_doing) = if let Ok(r) = self.routes.read() {
+                (
+                    r.get(&format!("STREAM {}", path)).cloned(),
+                    r.get(&format!("SOCKET {}", path)).cloned(),
+                )
+            } else {
+                (None, None)
+            };
+            let wants_socket = req
+                .header("Upgrade")
+                .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
 
-                let mut body_str = String::new();
-                let _ = request.as_reader().read_to_string(&mut body_str);
-                self.set_variable("RequestBody".to_string(), body_str);
+            if let (Some(doing), true) = (socket_doing, wants_socket) {
+                return Routed::Done(Dispatch::Socket(path, doing));
+            }
+            if let Some(doing) = stream_doing {
+                return Routed::Done(Dispatch::Stream(path, doing));
+            }
+        }
 
-                if let Some(doing_name) = matched_doing {
-                    let func_data = if let Ok(funcs) = self.functions.read() {
-                        funcs.get(&doing_name).cloned()
-                    } else {
-                        None
-                    };
+        // Proxy Routes (Longest prefix, forwarded through BigNet)
+        if let Some((prefix, upstream)) = self.match_proxy_route(&url) {
+            return Routed::Proxy(prefix, upstream);
+        }
 
-                    if let Some((_, func_tokens)) = func_data {
-                        self.run(func_tokens);
+        // Route Matching (Exact + Wildcard)
+        let mut matched_doing = None;
+        let mut matched_route = String::from("unmatched");
+        let mut request_extra = String::new();
+
+        let routes_snapshot = if let Ok(r) = self.routes.read() {
+            r.clone()
+        } else {
+            std::collections::HashMap::new()
+        };
+
+        for (route_key, doing) in routes_snapshot {
+            let parts: Vec<&str> = route_key.split_whitespace().collect();
+            if parts.len() < 2 {
+                continue;
+            }
+            let r_method = parts[0];
+            let r_path = parts[1];
+
+            if r_method == method {
+                if let Some(base) = r_path.strip_suffix('+') {
+                    if let Some(extra) = url.strip_prefix(base) {
+                        matched_doing = Some(doing.clone());
+                        matched_route = r_path.to_string();
+                        request_extra = extra.to_string();
+                        break;
                     }
+                } else if r_path == url.split('?').next().unwrap_or("") {
+                    matched_doing = Some(doing.clone());
+                    matched_route = r_path.to_string();
+                    break;
                 }
+            }
+        }
 
-                let resp_body = self
-                    .get_variable("Sbig_Response_Body")
-                    .unwrap_or_default();
-                let resp_file = self
-                    .get_variable("Sbig_Response_File")
-                    .unwrap_or_default();
+        Routed::Doing(matched_doing, matched_route, request_extra)
+    }
 
-                let mut response = if !resp_file.is_empty() {
-                    if let Ok(content) = fs::read_to_string(&resp_file) {
-                        let (final_content, content_type) = if resp_file.ends_with(".biew") {
-                            (super::biew::Biew::transpile_biew(&content), "text/html")
-                        } else if resp_file.ends_with(".bss") {
-                            (super::biew::Biew::transpile_bss(&content), "text/css")
-                        } else if resp_file.ends_with(".html") {
-                            (content, "text/html")
-                        } else {
-                            (content, "text/plain")
-                        };
-                        Response::from_string(final_content).with_header(
-                            Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes())
-                                .unwrap(),
-                        )
-                    } else {
-                        Response::from_string("404").with_status_code(404)
-                    }
+    // Runs the matched doing (nothing for unmatched requests) and builds its reply.
+    // Uploads still at their saved path afterwards are deleted; moving them keeps them.
+    fn run_route(
+        &mut self,
+        req: &SbigRequest,
+        matched_doing: Option<String>,
+        request_extra: String,
+    ) -> Response<Cursor<Vec<u8>>> {
+        self.set_variable("Sbig_Response_Body".to_string(), String::new());
+        self.set_variable("Sbig_Response_File".to_string(), String::new());
+        self.set_variable("RequestExtra".to_string(), request_extra);
+        self.current_status = 200;
+        self.current_headers.clear();
+
+        // Body (Decoded by Content-Type)
+        let (body_str, data, files) = match matched_doing {
+            Some(_) => self.decode_body(req),
+            None => (String::new(), String::from("{}"), Vec::new()),
+        };
+        self.set_variable("RequestBody".to_string(), body_str);
+        self.set_variable("RequestData".to_string(), data);
+        self.set_variable(
+            "RequestFiles".to_string(),
+            serde_json::Value::Array(files.clone()).to_string(),
+        );
+
+        if let Some(doing_name) = matched_doing {
+            let func_data = if let Ok(funcs) = self.functions.read() {
+                funcs.get(&doing_name).cloned()
+            } else {
+                None
+            };
+
+            if let Some((_, func_tokens)) = func_data {
+                self.run(func_tokens);
+            }
+        }
+        remove_uploads(&files);
+
+        let resp_body = self
+            .get_variable("Sbig_Response_Body")
+            .unwrap_or_default();
+        let resp_file = self
+            .get_variable("Sbig_Response_File")
+            .unwrap_or_default();
+
+        let mut response = if !resp_file.is_empty() {
+            if let Ok(content) = fs::read_to_string(&resp_file) {
+                let (final_content, content_type) = if resp_file.ends_with(".biew") {
+                    (self.render_biew_file(&resp_file, &content), "text/html")
+                } else if resp_file.ends_with(".bss") {
+                    (super::biew::Biew::transpile_bss(&content), "text/css")
+                } else if resp_file.ends_with(".html") {
+                    (content, "text/html")
                 } else {
-                    Response::from_string(resp_body)
+                    (content, "text/plain")
                 };
+                Response::from_string(final_content).with_header(
+                    Header::from_bytes(&b"Content-Type"[..], content_type.as_bytes()).unwrap(),
+                )
+            } else {
+                Response::from_string("404").with_status_code(404)
+            }
+        } else {
+            Response::from_string(resp_body)
+        };
 
-                // Apply Status & Headers
-                response = response.with_status_code(self.current_status);
-                for (k, v) in &self.current_headers {
-                    if let Ok(h) = Header::from_bytes(k.as_bytes(), v.as_bytes()) {
-                        response = response.with_header(h);
-                    }
-                }
+        // Apply Status & Headers
+        response = response.with_status_code(self.current_status);
+        for (k, v) in &self.current_headers {
+            if let Ok(h) = Header::from_bytes(k.as_bytes(), v.as_bytes()) {
+                response = response.with_header(h);
+            }
+        }
+
+        response
+    }
 
-                let _ = request.respond(response);
+    // Adds the CORS headers for an allowed Origin.
+    fn with_cors(
+        &self,
+        mut response: Response<Cursor<Vec<u8>>>,
+        req: &SbigRequest,
+    ) -> Response<Cursor<Vec<u8>>> {
+        if let Some(origin) = req.header("Origin") {
+            for (k, v) in self.cors_response_headers(&origin) {
+                if let Ok(h) = Header::from_bytes(k.as_bytes(), v.as_bytes()) {
+                    response = response.with_header(h);
+                }
             }
         }
+        response
     }
 
-    pub fn handle_reply(&mut self, i: &mut usize, tokens: &Vec<Token>) {
-        *i += 1;
-        if *i < tokens.len() {
-            match tokens[*i].token_type {
-                TokenType::With => {
-                    *i += 1;
-                    let val = if let TokenType::Identifier(ref name) = tokens[*i].token_type {
-                        // Direct Variable: Use as is (No double interpolation)
-                        self.get_variable(name).unwrap_or_default()
-                    } else {
+    // Sends the response, then updates the route metrics and writes the access log line,
+    // so the measured latency includes writing the answer.
+    // 'route' is None for requests that should stay out of the metrics (e.g. the metrics page itself).
+    fn finish_request(
+        &mut self,
+        request: Request,
+        response: Response<Cursor<Vec<u8>>>,
+        route: Option<&str>,
+        started: Instant,
+    ) {
+        let method = request.method().as_str().to_uppercase();
+        let status = response.status_code().0;
+        let bytes = response.data_length().unwrap_or(0);
+        let url = request.url().to_string();
+        let http_version = request.http_version().to_string();
+        let client = request
+            .remote_addr()
+            .map(|a| a.ip().to_string())
+            .unwrap_or_else(|| String::from("-"));
+
+        let _ = request.respond(response);
+        let elapsed = started.elapsed().as_secs_f64();
+
+        if let Some(route) = route {
+            let entry = self
+                .route_metrics
+                .entry(format!("{} {}", method, route))
+                .or_default();
+            *entry.statuses.entry(status).or_insert(0) += 1;
+            for (idx, bound) in LATENCY_BUCKETS.iter().enumerate() {
+                if elapsed <= *bound {
+                    entry.buckets[idx] += 1;
+                }
+            }
+            entry.sum += elapsed;
+            entry.count += 1;
+        }
+
+        // Logging
+        if let Some(ref log_path) = self.log_file {
+            use std::io::Write;
+            let now = chrono::Local::now();
+
+            let line = match self.log_format.as_str() {
+                // Common Log Format + request duration in microseconds
+                "clf" => format!(
+                    "{} - - [{}] \"{} {} HTTP/{}\" {} {} {}",
+                    client,
+                    now.format("%d/%b/%Y:%H:%M:%S %z"),
+                    method,
+                    url,
+                    http_version,
+                    status,
+                    if bytes == 0 {
+                        String::from("-")
+                    } else {
+                        bytes.to_string()
+                    },
+                    (elapsed * 1_000_000.0).round() as u64
+                ),
+                "json" => serde_json::json!({
+                    "time": now.to_rfc3339(),
+                    "client": client,
+                    "method": method,
+                    "path": url,
+                    "route": route.unwrap_or(""),
+                    "status": status,
+                    "bytes": bytes,
+                    "duration_ms": (elapsed * 1000.0 * 1000.0).round() / 1000.0,
+                })
+                .to_string(),
+                _ => format!(
+                    "[{}] {} {} {} {}b {:.3}ms {}",
+                    now,
+                    method,
+                    url,
+                    status,
+                    bytes,
+                    elapsed * 1000.0,
+                    client
+                ),
+            };
+
+            if let Ok(mut f) = std::fs::OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(log_path)
+            {
+                let _ = writeln!(f, "{}", line);
+            }
+        }
+    }
+
+    // Prometheus text exposition of the per-route counters and latency histograms.
+    fn render_metrics(&self) -> String {
+        let mut keys: Vec<&String> = self.route_metrics.keys().collect();
+        keys.sort();
+
+        let mut out = String::new();
+        out.push_str("# HELP sbig_requests_total Requests handled by Sbig.\n");
+        out.push_str("# TYPE sbig_requests_total counter\n");
+        for key in &keys {
+            let (method, route) = key.split_once(' ').unwrap_or(("", key));
+            let m = &self.route_metrics[*key];
+            let mut statuses: Vec<(&u16, &u64)> = m.statuses.iter().collect();
+            statuses.sort();
+            for (status, count) in statuses {
+                out.push_str(&format!(
+                    "sbig_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}\n",
+                    method,
+                    escape_label(route),
+                    status,
+                    count
+                ));
+            }
+        }
+
+        out.push_str("# HELP sbig_request_duration_seconds Request latency in seconds.\n");
+        out.push_str("# TYPE sbig_request_duration_seconds histogram\n");
+        for key in &keys {
+            let (method, route) = key.split_once(' ').unwrap_or(("", key));
+            let labels = format!("method=\"{}\",route=\"{}\"", method, escape_label(route));
+            let m = &self.route_metrics[*key];
+            for (idx, bound) in LATENCY_BUCKETS.iter().enumerate() {
+                out.push_str(&format!(
+                    "sbig_request_duration_seconds_bucket{{{},le=\"{}\"}} {}\n",
+                    labels, bound, m.buckets[idx]
+                ));
+            }
+            out.push_str(&format!(
+                "sbig_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}\n",
+                labels, m.count
+            ));
+            out.push_str(&format!(
+                "sbig_request_duration_seconds_sum{{{}}} {}\n",
+                labels, m.sum
+            ));
+            out.push_str(&format!(
+                "sbig_request_duration_seconds_count{{{}}} {}\n",
+                labels, m.count
+            ));
+        }
+        out
+    }
+
+    // Runs a server hook doing and hands back its ReturnValue.
+    // Depth and scopes are restored so an early 'return' cannot leak into the next request.
+    fn run_hook_doing(&mut self, doing_name: &str) -> Option<String> {
+        let func_data = if let Ok(funcs) = self.functions.read() {
+            funcs.get(doing_name).cloned()
+        } else {
+            None
+        };
+        let (_, func_tokens) = func_data?;
+
+        let depth = self.call_depth;
+        let scopes = self.local_scopes.len();
+        self.set_variable("ReturnValue".to_string(), String::new());
+        self.run(func_tokens);
+        self.return_triggered = false;
+        self.call_depth = depth;
+        self.local_scopes.truncate(scopes);
+        self.get_variable("ReturnValue")
+    }
+
+    // Splits a request body into (RequestBody, RequestData, RequestFiles).
+    // JSON and form bodies become maps; multipart files are written to the upload directory.
+    fn decode_body(&self, req: &SbigRequest) -> (String, String, Vec<serde_json::Value>) {
+        let content_type = req.header("Content-Type").unwrap_or_default();
+        let body = &req.body;
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_lowercase();
+        let empty_map = String::from("{}");
+        let no_files = Vec::new();
+
+        match mime.as_str() {
+            "application/json" => {
+                let text = String::from_utf8_lossy(body).to_string();
+                let data = match serde_json::from_str::<serde_json::Value>(&text) {
+                    Ok(v) => v.to_string(),
+                    Err(_) => empty_map,
+                };
+                (text, data, no_files)
+            }
+            "application/x-www-form-urlencoded" => {
+                let text = String::from_utf8_lossy(body).to_string();
+                let data = serde_json::Value::Object(parse_form(&text)).to_string();
+                (text, data, no_files)
+            }
+            "multipart/form-data" => {
+                let (fields, files) = match (&req.form, multipart_boundary(&content_type)) {
+                    (Some(form), _) => form.clone(),
+                    // Simulated requests arrive with the body in memory
+                    (None, Some(boundary)) => {
+                        parse_multipart(&mut Cursor::new(body), &boundary, &self.upload_path())
+                    }
+                    (None, None) => return (String::new(), empty_map, no_files),
+                };
+                (String::new(), serde_json::Value::Object(fields).to_string(), files)
+            }
+            _ => (String::from_utf8_lossy(body).to_string(), empty_map, no_files),
+        }
+    }
+
+    // Where multipart file parts are saved ('control uploads', else the system temp dir).
+    fn upload_path(&self) -> std::path::PathBuf {
+        match self.upload_dir {
+            Some(ref d) => std::path::PathBuf::from(d),
+            None => std::env::temp_dir().join("bigrun_uploads"),
+        }
+    }
+
+    // Access-Control headers for a simple (non-preflight) response. Empty if the origin is not allowed.
+    fn cors_response_headers(&self, origin: &str) -> Vec<(String, String)> {
+        let Some(ref policy) = self.cors else {
+            return Vec::new();
+        };
+        let origin = origin.trim_end_matches('/');
+        let any = policy.origins.iter().any(|o| o == "*");
+        if !any && !policy.origins.iter().any(|o| o == origin) {
+            return Vec::new();
+        }
+
+        let mut headers = Vec::new();
+        if any && !policy.credentials {
+            headers.push(("Access-Control-Allow-Origin".to_string(), "*".to_string()));
+        } else {
+            // Credentials forbid "*", so the origin is echoed and caches must key on it
+            headers.push(("Access-Control-Allow-Origin".to_string(), origin.to_string()));
+            headers.push(("Vary".to_string(), "Origin".to_string()));
+        }
+        if policy.credentials {
+            headers.push(("Access-Control-Allow-Credentials".to_string(), "true".to_string()));
+        }
+        headers
+    }
+
+    // Headers for a preflight answer (without the ones finish_request adds). None if the origin is not allowed.
+    fn cors_preflight_headers(&self, origin: &str, requested: &str) -> Option<Vec<(String, String)>> {
+        let policy = self.cors.as_ref()?;
+        if self.cors_response_headers(origin).is_empty() {
+            return None;
+        }
+        let allow_headers = if policy.headers.is_empty() {
+            requested.to_string()
+        } else {
+            policy.headers.clone()
+        };
+
+        let mut headers = vec![
+            ("Access-Control-Allow-Methods".to_string(), policy.methods.clone()),
+            ("Access-Control-Max-Age".to_string(), policy.max_age.to_string()),
+        ];
+        if !allow_headers.is_empty() {
+            headers.push(("Access-Control-Allow-Headers".to_string(), allow_headers));
+        }
+        Some(headers)
+    }
+
+    // Returns (prefix, upstream) of the longest proxy route covering the path.
+    fn match_proxy_route(&self, url: &str) -> Option<(String, String)> {
+        let path = url.split('?').next().unwrap_or("");
+        let routes = self.routes.read().ok()?;
+        routes
+            .iter()
+            .filter_map(|(key, upstream)| {
+                let prefix = key.strip_prefix("PROXY ")?;
+                prefix_matches(path, prefix).then(|| (prefix.to_string(), upstream.clone()))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+    }
+
+    // Forwards the request to 'upstream' + the part of the URL after 'prefix'.
+    // This runs on the request loop, so other requests wait for the upstream (up to the
+    // 30 second BigNet timeout); slow upstreams hold up the whole server.
+    // With a 'cors' policy the upstream's Access-Control headers are dropped, since with_cors adds ours.
+    fn forward_request(
+        &mut self,
+        req: &SbigRequest,
+        prefix: &str,
+        upstream: &str,
+    ) -> Response<Cursor<Vec<u8>>> {
+        let target = format!("{}{}", upstream.trim_end_matches('/'), &req.url[prefix.len()..]);
+        let mut headers: Vec<(String, String)> = req
+            .headers
+            .iter()
+            .filter(|(k, _)| !HOP_HEADERS.contains(&k.to_lowercase().as_str()))
+            .cloned()
+            .collect();
+        if let Some(host) = req.header("Host") {
+            headers.push(("X-Forwarded-Host".to_string(), host));
+        }
+        headers.push(("X-Forwarded-For".to_string(), req.client.clone()));
+        headers.push(("X-Forwarded-Proto".to_string(), "http".to_string()));
+
+        match self.net.forward(&req.method, &target, &headers, req.body.clone()) {
+            Ok((status, resp_headers, data)) => {
+                let mut response = Response::from_data(data).with_status_code(status);
+                for (k, v) in resp_headers {
+                    let name = k.to_lowercase();
+                    if HOP_HEADERS.contains(&name.as_str())
+                        || (self.cors.is_some() && name.starts_with("access-control-"))
+                    {
+                        continue;
+                    }
+                    if let Ok(h) = Header::from_bytes(k.as_bytes(), v.as_bytes()) {
+                        response = response.with_header(h);
+                    }
+                }
+                response
+            }
+            Err(e) => {
+                println!("{}", e);
+                Response::from_string("502 Bad Gateway").with_status_code(502)
+            }
+        }
+    }
+
+    // Runs a live doing (stream/socket/open/close) for one client.
+    // ClientId and SocketMessage live in their own scope so socket threads don't share them.
+    fn run_live_doing(&mut self, doing_name: &str, client_id: &str, message: Option<&str>) {
+        let previous = self.live_client.replace(client_id.to_string());
+        let mut scope = HashMap::new();
+        scope.insert("ClientId".to_string(), client_id.to_string());
+        if let Some(msg) = message {
+            scope.insert("SocketMessage".to_string(), msg.to_string());
+        }
+        self.local_scopes.push(scope);
+        self.run_hook_doing(doing_name);
+        self.local_scopes.pop();
+        self.live_client = previous;
+    }
+
+    fn run_lifecycle_hook(&mut self, event: &str, route: &str, client_id: &str) {
+        let hook = if let Ok(r) = self.routes.read() {
+            r.get(&format!("{} {}", event, route)).cloned()
+        } else {
+            None
+        };
+        if let Some(doing_name) = hook {
+            self.run_live_doing(&doing_name, client_id, None);
+        }
+    }
+
+    // SSE: keeps the connection open and hands the writer to its own thread.
+    fn open_stream(&mut self, request: Request, route: String, doing_name: String) {
+        let client_id = uuid::Uuid::new_v4().to_string();
+        let mut writer = request.into_writer();
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n";
+        if writer.write_all(head.as_bytes()).and_then(|_| writer.flush()).is_err() {
+            return;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        if let Ok(mut clients) = self.live_clients.lock() {
+            clients.insert(
+                client_id.clone(),
+                LiveClient {
+                    route: route.clone(),
+                    sink: LiveSink::Stream(tx),
+                    channels: HashSet::from([route.clone()]),
+                },
+            );
+        }
+
+        let mut worker = self.clone();
+        let (id, path) = (client_id.clone(), route.clone());
+        std::thread::spawn(move || {
+            worker.serve_stream(writer, rx, id, path);
+        });
+
+        self.run_lifecycle_hook("OPEN", &route, &client_id);
+        self.run_live_doing(&doing_name, &client_id, None);
+    }
+
+    // Writes queued pushes as SSE events, and a heartbeat comment when there is nothing to send.
+    // A failed write means the client has gone: it is dropped and its close hook runs.
+    fn serve_stream(
+        &mut self,
+        mut writer: Box<dyn Write + Send>,
+        outbox: Receiver<String>,
+        client_id: String,
+        route: String,
+    ) {
+        loop {
+            let frame = match outbox.recv_timeout(SSE_HEARTBEAT) {
+                Ok(msg) => {
+                    let mut frame = String::new();
+                    for line in msg.lines() {
+                        frame.push_str(&format!("data: {}\n", line));
+                    }
+                    frame.push('\n');
+                    frame
+                }
+                Err(RecvTimeoutError::Timeout) => String::from(": ping\n\n"),
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+            if writer.write_all(frame.as_bytes()).and_then(|_| writer.flush()).is_err() {
+                break;
+            }
+        }
+
+        if let Ok(mut clients) = self.live_clients.lock() {
+            clients.remove(&client_id);
+        }
+        self.run_lifecycle_hook("CLOSE", &route, &client_id);
+    }
+
+    // WebSocket: answers the handshake, then hands the connection to its own thread.
+    fn open_socket(&mut self, request: Request, route: String, doing_name: String) {
+        let key = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+            .map(|h| h.value.as_str().trim().to_string());
+        let Some(key) = key else {
+            let _ = request.respond(Response::from_string("400 Bad Request").with_status_code(400));
+            return;
+        };
+
+        let mut hasher = Sha1::new();
+        hasher.update(key.as_bytes());
+        hasher.update(WS_GUID.as_bytes());
+        let accept = general_purpose::STANDARD.encode(hasher.finalize());
+
+        let response = Response::empty(101).with_header(
+            Header::from_bytes(&b"Sec-WebSocket-Accept"[..], accept.as_bytes()).unwrap(),
+        );
+        let stream = request.upgrade("websocket", response);
+
+        let client_id = uuid::Uuid::new_v4().to_string();
+        let (tx, rx) = mpsc::channel();
+        if let Ok(mut clients) = self.live_clients.lock() {
+            clients.insert(
+                client_id.clone(),
+                LiveClient {
+                    route: route.clone(),
+                    sink: LiveSink::Socket(tx),
+                    channels: HashSet::from([route.clone()]),
+                },
+            );
+        }
+
+        let mut worker = self.clone();
+        std::thread::spawn(move || {
+            worker.serve_socket(stream, rx, client_id, route, doing_name);
+        });
+    }
+
+    // The only owner of the connection. tiny_http doesn't expose the socket, so a read can't time
+    // out; instead every read starts with a ping outstanding, and the client's pong ends the wait.
+    // Between reads the outbox is written, and after a pong pushes are sent as they arrive for up
+    // to SOCKET_POLL before the next ping. A client that vanishes never acknowledges the ping, so
+    // the listener's TCP user timeout fails the read.
+    fn serve_socket(
+        &mut self,
+        mut stream: Box<dyn ReadWrite + Send>,
+        outbox: Receiver<WsFrame>,
+        client_id: String,
+        route: String,
+        doing_name: String,
+    ) {
+        self.run_lifecycle_hook("OPEN", &route, &client_id);
+        let mut alive = flush_outbox(&mut stream, &outbox);
+
+        let mut message: Vec<u8> = Vec::new();
+        let mut pinged = false;
+        while alive {
+            if !pinged {
+                let until = Instant::now() + SOCKET_POLL;
+                while alive {
+                    match outbox.recv_timeout(until.saturating_duration_since(Instant::now())) {
+                        Ok((opcode, payload)) => {
+                            alive = write_ws_frame(&mut stream, opcode, &payload).is_ok();
+                        }
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Err(RecvTimeoutError::Disconnected) => alive = false,
+                    }
+                }
+                if !alive || write_ws_frame(&mut stream, 0x9, b"").is_err() {
+                    break;
+                }
+                pinged = true;
+            }
+
+            let (fin, opcode, payload) = match read_ws_frame(&mut stream) {
+                Ok(frame) => frame,
+                Err(_) => break,
+            };
+            match opcode {
+                // Text, Binary, Continuation
+                0x0..=0x2 => {
+                    message.extend_from_slice(&payload);
+                    if message.len() > WS_MAX_MESSAGE {
+                        let _ = write_ws_frame(&mut stream, 0x8, &1009u16.to_be_bytes());
+                        break;
+                    }
+                    if fin {
+                        let text = String::from_utf8_lossy(&message).to_string();
+                        message.clear();
+                        self.run_live_doing(&doing_name, &client_id, Some(&text));
+                    }
+                }
+                0x8 => {
+                    let _ = write_ws_frame(&mut stream, 0x8, &payload);
+                    break;
+                }
+                0x9 => {
+                    alive = write_ws_frame(&mut stream, 0xA, &payload).is_ok();
+                }
+                0xA => pinged = false,
+                _ => {}
+            }
+            alive = alive && flush_outbox(&mut stream, &outbox);
+        }
+
+        if let Ok(mut clients) = self.live_clients.lock() {
+            clients.remove(&client_id);
+        }
+        self.run_lifecycle_hook("CLOSE", &route, &client_id);
+    }
+
+    // Delivers a message to one client (Some(id)) or every member of a channel.
+    fn push_live(&mut self, client: Option<&str>, channel: Option<&str>, msg: &str) {
+        let mut dropped = Vec::new();
+        if let Ok(mut clients) = self.live_clients.lock() {
+            for (id, c) in clients.iter_mut() {
+                let wanted = match (client, channel) {
+                    (Some(target), _) => id == target,
+                    (None, Some(name)) => c.channels.contains(name),
+                    _ => false,
+                };
+                if !wanted {
+                    continue;
+                }
+                let sent = match c.sink {
+                    LiveSink::Stream(ref tx) => tx.send(msg.to_string()).is_ok(),
+                    LiveSink::Socket(ref tx) => tx.send((0x1, msg.as_bytes().to_vec())).is_ok(),
+                };
+                if !sent {
+                    dropped.push(id.clone());
+                }
+            }
+            // A closed outbox means its connection thread has ended (and ran the close hook)
+            for id in &dropped {
+                clients.remove(id);
+            }
+        }
+    }
+
+    pub fn handle_push(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        // Syntax:
+        // push "msg" to client {Id}
+        // push "msg" to channel "room"
+        // push join "room"  /  push leave "room"  (Current client)
+        *i += 1; // Skip "push"
+        if *i >= tokens.len() {
+            return;
+        }
+
+        if let TokenType::Identifier(ref word) = tokens[*i].token_type {
+            if (word == "join" || word == "leave") && *i + 1 < tokens.len() {
+                let joining = word == "join";
+                *i += 1;
+                let room_raw = self.get_token_value(&tokens[*i]);
+                let room = self.interpolate_string(&room_raw);
+                let Some(id) = self.live_client.clone() else {
+                    println!("Big Error: 'push {}' only works inside a live doing.", word);
+                    return;
+                };
+                if let Ok(mut clients) = self.live_clients.lock() {
+                    if let Some(c) = clients.get_mut(&id) {
+                        if joining {
+                            c.channels.insert(room);
+                        } else {
+                            c.channels.remove(&room);
+                        }
+                    }
+                }
+                return;
+            }
+        }
+
+        let msg_raw = self.get_token_value(&tokens[*i]);
+        let msg = self.interpolate_string(&msg_raw);
+        if *i + 3 < tokens.len() && tokens[*i + 1].token_type == TokenType::To {
+            *i += 2;
+            let target = self.get_token_raw_name(&tokens[*i]).to_lowercase();
+            *i += 1;
+            if tokens[*i].token_type == TokenType::LBrace && *i + 2 < tokens.len() {
+                *i += 1;
+            }
+            let name = self.get_token_value(&tokens[*i]);
+            if *i + 1 < tokens.len() && tokens[*i + 1].token_type == TokenType::RBrace {
+                *i += 1;
+            }
+            let name = self.interpolate_string(&name);
+            match target.as_str() {
+                "client" => self.push_live(Some(&name), None, &msg),
+                "channel" => self.push_live(None, Some(&name), &msg),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn handle_simulate(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        // Syntax:
+        // simulate server  (Next 'start server' loads routes without binding)
+        // simulate get "/path" [with "body"] [note "Key" as "Value"]... & set as {Res}
+        *i += 1; // Skip "simulate"
+        if *i >= tokens.len() {
+            return;
+        }
+
+        let method = match tokens[*i].token_type {
+            TokenType::Server => {
+                self.sbig_simulate = true;
+                return;
+            }
+            TokenType::Get => String::from("GET"),
+            TokenType::Post => String::from("POST"),
+            TokenType::Delete => String::from("DELETE"),
+            _ => self.get_token_raw_name(&tokens[*i]).to_uppercase(),
+        };
+        if *i + 1 >= tokens.len() {
+            println!("Big Error: 'simulate {}' needs a path.", method.to_lowercase());
+            return;
+        }
+        *i += 1;
+        let url_raw = self.get_token_value(&tokens[*i]);
+        let url = self.interpolate_string(&url_raw);
+
+        let mut body = String::new();
+        let mut headers = Vec::new();
+        while *i + 2 < tokens.len() {
+            match tokens[*i + 1].token_type {
+                TokenType::With => {
+                    *i += 2;
+                    body = if let TokenType::Identifier(ref name) = tokens[*i].token_type {
+                        self.get_variable(name).unwrap_or_default()
+                    } else {
+                        let text = self.get_token_value(&tokens[*i]);
+                        self.interpolate_string(&text)
+                    };
+                }
+                TokenType::Note if *i + 4 < tokens.len() && tokens[*i + 3].token_type == TokenType::As => {
+                    let key = self.get_token_value(&tokens[*i + 2]);
+                    let val_raw = self.get_token_value(&tokens[*i + 4]);
+                    headers.push((key, self.interpolate_string(&val_raw)));
+                    *i += 4;
+                }
+                _ => break,
+            }
+        }
+
+        // The simulated doing runs like a served one, so keep the caller's scopes intact
+        let saved_depth = self.call_depth;
+        let saved_scopes = self.local_scopes.len();
+        let res = self.simulate_request(&method, &url, headers, body.into_bytes());
+        self.return_triggered = false;
+        self.call_depth = saved_depth;
+        self.local_scopes.truncate(saved_scopes);
+
+        let mut header_map = serde_json::Map::new();
+        for (k, v) in res.headers {
+            header_map.insert(k, serde_json::Value::String(v));
+        }
+        let result = serde_json::json!({
+            "status": res.status,
+            "headers": header_map,
+            "body": String::from_utf8_lossy(&res.body),
+        });
+        self.handle_set_as_multiple(i, tokens, vec![result.to_string()]);
+    }
+
+    // Returns Some(seconds) when the client must wait (429), None when the request may pass.
+    fn check_rate_limit(&mut self, url: &str, client: &str) -> Option<u64> {
+        let path = url.split('?').next().unwrap_or("");
+
+        // Most specific rule wins (Longest matching prefix)
+        let rule = self
+            .rate_rules
+            .iter()
+            .filter(|r| prefix_matches(path, &r.prefix))
+            .max_by_key(|r| r.prefix.len())
+            .cloned()?;
+
+        let mut client_key = client.to_string();
+        if let Some(doing_name) = self.rate_key_doing.clone() {
+            if let Some(chosen) = self.run_hook_doing(&doing_name) {
+                if !chosen.is_empty() && chosen != "nothing" {
+                    client_key = chosen;
+                }
+            }
+        }
+
+        let now = Instant::now();
+
+        // Forget idle clients whose buckets have refilled anyway (at most once every 10s)
+        if self.rate_buckets.len() > 10_000 && now.duration_since(self.rate_pruned).as_secs() >= 10 {
+            self.rate_pruned = now;
+            let rules = self.rate_rules.clone();
+            self.rate_buckets.retain(|k, b| {
+                let prefix = k.split('|').next().unwrap_or("");
+                match rules.iter().find(|r| r.prefix == prefix) {
+                    Some(r) => {
+                        b.tokens + now.duration_since(b.last).as_secs_f64() * r.rate < r.burst
+                    }
+                    None => false,
+                }
+            });
+        }
+
+        let bucket = self
+            .rate_buckets
+            .entry(format!("{}|{}", rule.prefix, client_key))
+            .or_insert(RateBucket {
+                tokens: rule.burst,
+                last: now,
+            });
+
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rule.rate).min(rule.burst);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(((1.0 - bucket.tokens) / rule.rate).ceil().max(1.0) as u64)
+        }
+    }
+
+    pub fn handle_reply(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        *i += 1;
+        if *i < tokens.len() {
+            match tokens[*i].token_type {
+                TokenType::With => {
+                    *i += 1;
+                    let val = if let TokenType::Identifier(ref name) = tokens[*i].token_type {
+                        // Direct Variable: Use as is (No double interpolation)
+                        self.get_variable(name).unwrap_or_default()
+                    } else {
                         // Literal or Expression: Interpolate once
                         let text = self.get_token_value(&tokens[*i]);
                         self.interpolate_string(&text)
                     };
+                    // Inside a live doing the reply goes straight to that client
+                    if let Some(id) = self.live_client.clone() {
+                        self.push_live(Some(&id), None, &val);
+                        return;
+                    }
                     self.set_variable("Sbig_Response_Body".to_string(), val);
                     self.set_variable("Sbig_Response_File".to_string(), String::new());
                 }
@@ -311,9 +1853,22 @@ impl Interpreter {
                         self.current_headers.insert(key, val);
                     }
                 }
-                TokenType::Identifier(ref s) if s == "file" => {
+                TokenType::File => {
                     *i += 1;
                     let filename = self.get_token_value(&tokens[*i]);
+
+                    // Templates render now, while the doing's variables are still in scope
+                    if filename.ends_with(".biew") {
+                        if let Ok(content) = fs::read_to_string(&filename) {
+                            let html = self.render_biew_file(&filename, &content);
+                            self.set_variable("Sbig_Response_Body".to_string(), html);
+                            self.set_variable("Sbig_Response_File".to_string(), String::new());
+                            self.current_headers
+                                .insert("Content-Type".to_string(), "text/html".to_string());
+                            return;
+                        }
+                    }
+
                     self.set_variable("Sbig_Response_File".to_string(), filename);
                     self.set_variable("Sbig_Response_Body".to_string(), String::new());
                 }
@@ -322,3 +1877,488 @@ impl Interpreter {
         }
     }
 }
+
+// "/api" covers "/api" and "/api/users", but not "/apix" or "/api-docs".
+fn prefix_matches(path: &str, prefix: &str) -> bool {
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/'),
+        None => false,
+    }
+}
+
+// "integer" -> {"type": "integer"}; JSON schemas pass through.
+fn schema_value(schema: &str) -> serde_json::Value {
+    if schema.trim_start().starts_with('{') {
+        if let Ok(v) = serde_json::from_str(schema) {
+            return v;
+        }
+    }
+    serde_json::json!({ "type": schema })
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Decodes "a=1&b=two+words" into a map. Repeated keys keep the last value.
+fn parse_form(text: &str) -> serde_json::Map<String, serde_json::Value> {
+    let mut map = serde_json::Map::new();
+    for pair in text.split('&').filter(|p| !p.is_empty()) {
+        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+        let decode = |s: &str| {
+            percent_decode_str(&s.replace('+', " "))
+                .decode_utf8_lossy()
+                .to_string()
+        };
+        map.insert(decode(k), serde_json::Value::String(decode(v)));
+    }
+    map
+}
+
+// The boundary of a multipart/form-data Content-Type, if it is one.
+fn multipart_boundary(content_type: &str) -> Option<String> {
+    if !content_type.trim_start().to_lowercase().starts_with("multipart/form-data") {
+        return None;
+    }
+    content_type
+        .split(';')
+        .filter_map(|p| p.trim().strip_prefix("boundary="))
+        .next()
+        .map(|b| b.trim_matches('"').to_string())
+}
+
+// Reads a body up to 'left' bytes and notes whether the client sent more than that.
+struct CappedReader<R> {
+    inner: R,
+    left: usize,
+    exceeded: bool,
+}
+
+impl<R: Read> Read for CappedReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        if self.left == 0 {
+            let mut probe = [0u8; 1];
+            if self.inner.read(&mut probe)? > 0 {
+                self.exceeded = true;
+            }
+            return Ok(0);
+        }
+        let n = out.len().min(self.left);
+        let read = self.inner.read(&mut out[..n])?;
+        self.left -= read;
+        Ok(read)
+    }
+}
+
+const MULTIPART_CHUNK: usize = 64 * 1024;
+const MULTIPART_MAX_HEAD: usize = 16 * 1024;
+
+// Where the content of the current part goes while it is read.
+enum PartSink {
+    Field(Vec<u8>),
+    File(std::io::BufWriter<fs::File>, std::path::PathBuf, usize),
+    Skip,
+}
+
+// Reads a multipart/form-data body part by part. File parts are written to 'dir' as they
+// arrive, so only about one chunk of the body is held in memory. A malformed or cut-off
+// body keeps the parts that were complete before the problem.
+fn parse_multipart<R: Read + ?Sized>(
+    reader: &mut R,
+    boundary: &str,
+    dir: &std::path::Path,
+) -> MultipartForm {
+    let mut fields = serde_json::Map::new();
+    let mut files = Vec::new();
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut closing = b"\r\n".to_vec();
+    closing.extend_from_slice(&delimiter);
+    let mut buf: Vec<u8> = Vec::new();
+
+    // Skip the preamble up to the first delimiter
+    loop {
+        if let Some(p) = find_bytes(&buf, &delimiter, 0) {
+            buf.drain(..p + delimiter.len());
+            break;
+        }
+        buf.drain(..buf.len().saturating_sub(delimiter.len()));
+        if !fill_chunk(reader, &mut buf) {
+            return (fields, files);
+        }
+    }
+
+    // Each part: CRLF, headers, blank line, content, CRLF, delimiter. "--" after a delimiter ends the body.
+    loop {
+        while buf.len() < 2 {
+            if !fill_chunk(reader, &mut buf) {
+                return (fields, files);
+            }
+        }
+        if !buf.starts_with(b"\r\n") {
+            break;
+        }
+        let head_end = loop {
+            if let Some(p) = find_bytes(&buf, b"\r\n\r\n", 2) {
+                break p;
+            }
+            if buf.len() > MULTIPART_MAX_HEAD || !fill_chunk(reader, &mut buf) {
+                return (fields, files);
+            }
+        };
+        let head = String::from_utf8_lossy(&buf[2..head_end]).to_string();
+        buf.drain(..head_end + 4);
+
+        let mut name = String::new();
+        let mut filename = None;
+        let mut part_type = String::from("text/plain");
+        for line in head.lines() {
+            let (key, value) = line.split_once(':').unwrap_or((line, ""));
+            match key.trim().to_lowercase().as_str() {
+                "content-disposition" => {
+                    for attr in value.split(';').map(|a| a.trim()) {
+                        if let Some(v) = attr.strip_prefix("name=") {
+                            name = v.trim_matches('"').to_string();
+                        } else if let Some(v) = attr.strip_prefix("filename=") {
+                            filename = Some(v.trim_matches('"').to_string());
+                        }
+                    }
+                }
+                "content-type" => part_type = value.trim().to_string(),
+                _ => {}
+            }
+        }
+
+        let mut sink = match filename {
+            Some(ref original) => {
+                // Only keep the last path segment and safe characters of the client's name
+                let base = original.rsplit(['/', '\\']).next().unwrap_or("");
+                let safe: String = base
+                    .chars()
+                    .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+                    .collect();
+                let stored = dir.join(format!("{}-{}", uuid::Uuid::new_v4(), safe));
+                match fs::create_dir_all(dir).and_then(|_| fs::File::create(&stored)) {
+                    Ok(f) => PartSink::File(std::io::BufWriter::new(f), stored, 0),
+                    Err(_) => {
+                        println!("Big Error: Could not save upload '{}'", original);
+                        PartSink::Skip
+                    }
+                }
+            }
+            None => PartSink::Field(Vec::new()),
+        };
+
+        // Content runs up to CRLF + delimiter. Bytes that could be the start of it stay in 'buf'.
+        let complete = loop {
+            let (end, next) = match find_bytes(&buf, &closing, 0) {
+                Some(p) => (p, Some(p + closing.len())),
+                None => (buf.len().saturating_sub(closing.len() - 1), None),
+            };
+            sink = write_part(sink, &buf[..end]);
+            buf.drain(..next.unwrap_or(end));
+            if next.is_some() {
+                break true;
+            }
+            if !fill_chunk(reader, &mut buf) {
+                break false;
+            }
+        };
+
+        match sink {
+            PartSink::Field(content) if complete => {
+                fields.insert(
+                    name,
+                    serde_json::Value::String(String::from_utf8_lossy(&content).to_string()),
+                );
+            }
+            PartSink::File(mut f, stored, size) => {
+                if complete && f.flush().is_ok() {
+                    files.push(serde_json::json!({
+                        "field": name,
+                        "name": filename.unwrap_or_default(),
+                        "path": stored.to_string_lossy(),
+                        "size": size,
+                        "type": part_type,
+                    }));
+                } else {
+                    drop(f);
+                    let _ = fs::remove_file(&stored);
+                }
+            }
+            _ => {}
+        }
+        if !complete {
+            break;
+        }
+    }
+    (fields, files)
+}
+
+fn write_part(sink: PartSink, data: &[u8]) -> PartSink {
+    match sink {
+        PartSink::Field(mut content) => {
+            content.extend_from_slice(data);
+            PartSink::Field(content)
+        }
+        PartSink::File(mut f, stored, size) => {
+            if f.write_all(data).is_ok() {
+                PartSink::File(f, stored, size + data.len())
+            } else {
+                println!("Big Error: Could not save upload '{}'", stored.to_string_lossy());
+                drop(f);
+                let _ = fs::remove_file(&stored);
+                PartSink::Skip
+            }
+        }
+        PartSink::Skip => PartSink::Skip,
+    }
+}
+
+// Appends up to one chunk from the reader. False at the end of the body (or on a read error).
+fn fill_chunk<R: Read + ?Sized>(reader: &mut R, buf: &mut Vec<u8>) -> bool {
+    let start = buf.len();
+    buf.resize(start + MULTIPART_CHUNK, 0);
+    let read = reader.read(&mut buf[start..]).unwrap_or(0);
+    buf.truncate(start + read);
+    read > 0
+}
+
+// Deletes the saved files of a multipart body: when it was refused after all (e.g. too large),
+// or once its doing has finished. Files the doing moved elsewhere are already gone.
+fn remove_uploads(files: &[serde_json::Value]) {
+    for file in files {
+        if let Some(path) = file["path"].as_str() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn find_bytes(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    if from > haystack.len() {
+        return None;
+    }
+    haystack[from..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|p| p + from)
+}
+
+// Reads one WebSocket frame: (fin, opcode, unmasked payload).
+fn read_ws_frame(stream: &mut Box<dyn ReadWrite + Send>) -> std::io::Result<(bool, u8, Vec<u8>)> {
+    let mut head = [0u8; 2];
+    stream.read_exact(&mut head)?;
+    let fin = head[0] & 0x80 != 0;
+    let opcode = head[0] & 0x0f;
+    let masked = head[1] & 0x80 != 0;
+
+    let len = match head[1] & 0x7f {
+        126 => {
+            let mut ext = [0u8; 2];
+            stream.read_exact(&mut ext)?;
+            u16::from_be_bytes(ext) as usize
+        }
+        127 => {
+            let mut ext = [0u8; 8];
+            stream.read_exact(&mut ext)?;
+            u64::from_be_bytes(ext) as usize
+        }
+        n => n as usize,
+    };
+    if len > WS_MAX_MESSAGE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+
+    let mut mask = [0u8; 4];
+    if masked {
+        stream.read_exact(&mut mask)?;
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload)?;
+    if masked {
+        for (idx, b) in payload.iter_mut().enumerate() {
+            *b ^= mask[idx % 4];
+        }
+    }
+    Ok((fin, opcode, payload))
+}
+
+// Writes one unmasked, unfragmented frame (servers never mask).
+fn write_ws_frame<W: Write + ?Sized>(stream: &mut W, opcode: u8, payload: &[u8]) -> std::io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        n if n < 126 => frame.push(n as u8),
+        n if n <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            frame.push(127);
+            frame.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame)?;
+    stream.flush()
+}
+
+// Sends every queued frame for a socket client. False when the connection is gone.
+fn flush_outbox(stream: &mut Box<dyn ReadWrite + Send>, outbox: &Receiver<WsFrame>) -> bool {
+    while let Ok((opcode, payload)) = outbox.try_recv() {
+        if write_ws_frame(stream, opcode, &payload).is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+// Binds the server socket. Accepted connections inherit its TCP user timeout (Linux), so a client
+// that stops acknowledging what we send (e.g. a socket ping) is dropped instead of blocking its thread.
+fn bind_listener(addr: &str) -> std::io::Result<TcpListener> {
+    let listener = TcpListener::bind(addr)?;
+    #[cfg(target_os = "linux")]
+    socket2::SockRef::from(&listener).set_tcp_user_timeout(Some(TCP_USER_TIMEOUT))?;
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Loads an app the way 'simulate server' + 'use "app.big"' would.
+    fn app(source: &str) -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        interpreter.sbig_simulate = true;
+        interpreter.run(Lexer::new(source).tokenize());
+        interpreter
+    }
+
+    fn body(res: &SimResponse) -> String {
+        String::from_utf8_lossy(&res.body).to_string()
+    }
+
+    #[test]
+    fn routes_exact_and_prefix_paths() {
+        let mut web = app(r#"
+use web
+start doing Hello
+    reply with "hello"
+end doing
+start doing Files
+    reply with "extra=$RequestExtra"
+end doing
+on get "/hello" run Hello
+on get "/files/+" run Files
+"#);
+        let res = web.simulate_request("get", "/hello?x=1", Vec::new(), Vec::new());
+        assert_eq!((res.status, body(&res).as_str()), (200, "hello"));
+
+        let res = web.simulate_request("GET", "/files/a/b.txt", Vec::new(), Vec::new());
+        assert_eq!(body(&res), "extra=a/b.txt");
+
+        // Other methods don't run the doing
+        let res = web.simulate_request("POST", "/hello", Vec::new(), Vec::new());
+        assert_eq!(body(&res), "");
+    }
+
+    #[test]
+    fn rate_limit_prefix_stops_at_segments() {
+        let mut web = app(r#"
+use web
+control limit 1 per mins on "/api"
+start doing Ok
+    reply with "ok"
+end doing
+on get "/api/users" run Ok
+on get "/apix" run Ok
+"#);
+        for _ in 0..3 {
+            let res = web.simulate_request("GET", "/apix", Vec::new(), Vec::new());
+            assert_eq!(res.status, 200);
+        }
+        let first = web.simulate_request("GET", "/api/users", Vec::new(), Vec::new());
+        let second = web.simulate_request("GET", "/api/users", Vec::new(), Vec::new());
+        assert_eq!((first.status, second.status), (200, 429));
+    }
+
+    #[test]
+    fn body_limit_and_multipart_fields() {
+        let mut web = app(r#"
+use web
+control body 1 kb
+control uploads @"target/test_uploads"
+start doing Echo
+    reply with "$RequestData"
+end doing
+on post "/echo" run Echo
+"#);
+        let res = web.simulate_request("POST", "/echo", Vec::new(), vec![b'x'; 2048]);
+        assert_eq!(res.status, 413);
+
+        let form = "--XY\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nhello\r\n--XY--\r\n";
+        let headers = vec![(
+            String::from("Content-Type"),
+            String::from("multipart/form-data; boundary=XY"),
+        )];
+        let res = web.simulate_request("POST", "/echo", headers, form.as_bytes().to_vec());
+        assert_eq!(body(&res), r#"{"a":"hello"}"#);
+    }
+
+    #[test]
+    fn uploads_saved_for_matched_routes_only_and_removed_after() {
+        let dir = std::env::temp_dir().join(format!("bigrun_test_{}", uuid::Uuid::new_v4()));
+        let mut web = app(&format!(
+            r#"
+use web
+control uploads @"{}"
+start doing Files
+    reply with "$RequestFiles"
+end doing
+on post "/files" run Files
+"#,
+            dir.display()
+        ));
+        let form = "--XY\r\nContent-Disposition: form-data; name=\"f\"; filename=\"a.txt\"\r\n\r\nhello\r\n--XY--\r\n";
+        let headers = || {
+            vec![(
+                String::from("Content-Type"),
+                String::from("multipart/form-data; boundary=XY"),
+            )]
+        };
+
+        // Unmatched requests never touch the upload directory
+        web.simulate_request("POST", "/nowhere", headers(), form.as_bytes().to_vec());
+        assert!(!dir.exists());
+
+        // The doing sees the saved file; it is gone once the doing has finished
+        let res = web.simulate_request("POST", "/files", headers(), form.as_bytes().to_vec());
+        let files: serde_json::Value = serde_json::from_slice(&res.body).unwrap_or_default();
+        let path = files[0]["path"].as_str().unwrap_or_default().to_string();
+        assert!(path.ends_with("a.txt"));
+        assert!(!std::path::Path::new(&path).exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn multipart_streams_file_parts_to_disk() {
+        let dir = std::env::temp_dir().join(format!("bigrun_test_{}", uuid::Uuid::new_v4()));
+        let content = vec![7u8; 3 * MULTIPART_CHUNK + 5];
+        let mut data = b"--B\r\nContent-Disposition: form-data; name=\"f\"; filename=\"../a b.bin\"\r\n\r\n".to_vec();
+        data.extend_from_slice(&content);
+        data.extend_from_slice(b"\r\n--B--\r\n");
+
+        let (fields, files) = parse_multipart(&mut Cursor::new(data), "B", &dir);
+        assert!(fields.is_empty());
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0]["size"], content.len());
+        let path = files[0]["path"].as_str().unwrap_or_default().to_string();
+        assert!(path.ends_with("-ab.bin"));
+        assert_eq!(fs::read(&path).unwrap_or_default(), content);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,11 +1,24 @@
 use super::Interpreter;
 use crate::tokens::{Token, TokenType};
 use aes::Aes256;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
 use base64::{engine::general_purpose, Engine as _};
 use block_padding::Pkcs7;
+use ed25519_dalek::pkcs8::spki::der::pem::LineEnding;
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
 use cbc::{Decryptor, Encryptor};
-use rand::{thread_rng, Rng};
+use data_encoding::BASE32;
+use hmac::{Hmac, Mac};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use rand::rngs::OsRng;
+use rand::{thread_rng, Rng, RngCore};
+use sha2::{Digest, Sha256, Sha512};
+use std::io::Read;
 
 type Aes256CbcDec = Decryptor<Aes256>;
 type Aes256CbcEnc = Encryptor<Aes256>;
@@ -22,6 +35,14 @@ impl Interpreter {
             TokenType::Decode => self.handle_bit_decode(i, tokens),
             TokenType::Aes => self.handle_bit_aes(i, tokens),
             TokenType::Demon => self.handle_bit_demon(i, tokens),
+            TokenType::Identifier(ref w) if w.eq_ignore_ascii_case("hash") => self.handle_bit_hash(i, tokens, false),
+            TokenType::Identifier(ref w) if w.eq_ignore_ascii_case("hmac") => self.handle_bit_hash(i, tokens, true),
+            TokenType::Identifier(ref w) if w.eq_ignore_ascii_case("password") => self.handle_bit_password(i, tokens),
+            TokenType::Identifier(ref w) if w.eq_ignore_ascii_case("token") => self.handle_bit_token(i, tokens),
+            TokenType::Identifier(ref w) if w.eq_ignore_ascii_case("keypair") => self.handle_bit_keypair(i, tokens),
+            TokenType::Identifier(ref w) if w.eq_ignore_ascii_case("jwt") => self.handle_bit_jwt(i, tokens),
+            TokenType::Identifier(ref w) if w.eq_ignore_ascii_case("sign") => self.handle_bit_sign(i, tokens, false),
+            TokenType::Identifier(ref w) if w.eq_ignore_ascii_case("verify") => self.handle_bit_sign(i, tokens, true),
             _ => {}
         }
     }
@@ -37,11 +58,27 @@ impl Interpreter {
         }
     }
 
+    // Like 'skip_braces_and_get_value', but only string literals are interpolated,
+    // so stored values containing '$' (hashes, blobs) pass through untouched.
+    fn bit_value(&self, i: &mut usize, tokens: &Vec<Token>) -> String {
+        let literal = matches!(tokens[*i].token_type, TokenType::String(_));
+        let raw = self.skip_braces_and_get_value(i, tokens);
+        if literal {
+            self.interpolate_string(&raw)
+        } else {
+            raw
+        }
+    }
+
     fn handle_bit_code(&mut self, i: &mut usize, tokens: &Vec<Token>) {
         *i += 1; // Skip "code"
         let text_raw = self.skip_braces_and_get_value(i, tokens);
         let text = self.interpolate_string(&text_raw);
         *i += 1;
+        if *i < tokens.len() && tokens[*i].token_type == TokenType::As {
+            self.handle_bit_encoding(i, tokens, &text, true);
+            return;
+        }
         if *i < tokens.len() && tokens[*i].token_type == TokenType::With {
             *i += 1;
             let key_raw = self.skip_braces_and_get_value(i, tokens);
@@ -59,6 +96,10 @@ impl Interpreter {
         let text_raw = self.skip_braces_and_get_value(i, tokens);
         let text = self.interpolate_string(&text_raw);
         *i += 1;
+        if *i < tokens.len() && tokens[*i].token_type == TokenType::As {
+            self.handle_bit_encoding(i, tokens, &text, false);
+            return;
+        }
         if *i < tokens.len() && tokens[*i].token_type == TokenType::With {
             *i += 1;
             let key_raw = self.skip_braces_and_get_value(i, tokens);
@@ -71,6 +112,24 @@ impl Interpreter {
         }
     }
 
+    fn handle_bit_encoding(&mut self, i: &mut usize, tokens: &Vec<Token>, text: &str, encode: bool) {
+        // Syntax: bit code|decode "Text" as base64 | base64url | base32 | hex | url | html & set as {Result}
+        *i += 1; // Skip "as"
+        if *i >= tokens.len() {
+            return;
+        }
+        let format = self.get_token_raw_name(&tokens[*i]).to_lowercase();
+        let result = if encode {
+            encode_text(text, &format)
+        } else {
+            decode_text(text, &format)
+        };
+        match result {
+            Ok(value) => self.handle_set_as_multiple(i, tokens, vec![value]),
+            Err(e) => self.bit_bug(i, tokens, e),
+        }
+    }
+
     fn handle_bit_demon(&mut self, i: &mut usize, tokens: &Vec<Token>) {
         *i += 1; // Skip "demon"
         let text = self.skip_braces_and_get_value(i, tokens);
@@ -95,9 +154,29 @@ impl Interpreter {
         *i += 1; // Skip "aes"
         let mode = tokens[*i].token_type.clone();
         *i += 1; // Skip encrypt/decrypt
+        let data_at = *i;
         let data = self.skip_braces_and_get_value(i, tokens);
         *i += 1;
 
+        // Passphrase mode: bit aes encrypt|decrypt "[Data]" with "[Passphrase]" (AES-256-GCM)
+        if *i < tokens.len() && tokens[*i].token_type == TokenType::With {
+            *i += 1;
+            let pass = self.bit_value(i, tokens);
+            let mut at = data_at;
+            let data = self.bit_value(&mut at, tokens);
+            let result = if mode == TokenType::Decrypt {
+                gcm_open(&data, pass.as_bytes())
+            } else {
+                gcm_seal(&data, pass.as_bytes())
+            };
+            let value = result.unwrap_or_else(|e| {
+                self.bit_bug(i, tokens, e);
+                String::new()
+            });
+            self.handle_set_as_multiple(i, tokens, vec![value]);
+            return;
+        }
+
         if *i < tokens.len() && tokens[*i].token_type == TokenType::Key {
             *i += 1;
             let key_str = self.skip_braces_and_get_value(i, tokens);
@@ -116,6 +195,377 @@ impl Interpreter {
         }
     }
 
+    fn handle_bit_hash(&mut self, i: &mut usize, tokens: &Vec<Token>, is_hmac: bool) {
+        // Syntax:
+        // bit hash [file] "Text" [sha256 | sha512 | blake3] [hex | base64]
+        // bit hmac [file] "Text" with "Secret" [sha256 | sha512] [hex | base64] [check {Given}]
+        *i += 1; // Skip "hash" / "hmac"
+        if *i >= tokens.len() {
+            return;
+        }
+        let from_file = tokens[*i].token_type == TokenType::File;
+        if from_file {
+            *i += 1;
+        }
+        let text = self.bit_value(i, tokens);
+
+        let mut algo = String::from("sha256");
+        let mut base64 = false;
+        let mut secret = None;
+        let mut expected = None;
+        let line = tokens[*i].line;
+        while *i + 1 < tokens.len() && tokens[*i + 1].line == line && tokens[*i + 1].token_type != TokenType::Ampersand {
+            *i += 1;
+            match &tokens[*i].token_type {
+                TokenType::With if *i + 1 < tokens.len() => {
+                    *i += 1;
+                    secret = Some(self.bit_value(i, tokens));
+                }
+                TokenType::Check if *i + 1 < tokens.len() => {
+                    *i += 1;
+                    expected = Some(self.bit_value(i, tokens));
+                }
+                TokenType::Identifier(w) => match w.to_lowercase().as_str() {
+                    "hex" => base64 = false,
+                    "base64" => base64 = true,
+                    other => algo = other.to_string(),
+                },
+                _ => {}
+            }
+        }
+
+        if is_hmac && secret.is_none() {
+            self.bit_bug(i, tokens, String::from("HMAC needs 'with \"Secret\"'."));
+            return;
+        }
+        let input: Box<dyn Read> = if from_file {
+            match std::fs::File::open(&text) {
+                Ok(f) => Box::new(f),
+                Err(e) => {
+                    self.bit_bug(i, tokens, format!("Could not read '{}'. {}", text, e));
+                    return;
+                }
+            }
+        } else {
+            Box::new(std::io::Cursor::new(text.into_bytes()))
+        };
+        let secret_bytes = secret.as_deref().map(|s| s.as_bytes());
+        let digest = match digest_reader(&algo, secret_bytes, input) {
+            Ok(digest) => digest,
+            Err(e) => {
+                self.bit_bug(i, tokens, e);
+                return;
+            }
+        };
+        let result = match expected {
+            // Constant-time check of a given signature (e.g. a webhook header)
+            Some(given) => {
+                let given_bytes = if base64 {
+                    general_purpose::STANDARD.decode(given.trim()).unwrap_or_default()
+                } else {
+                    hex::decode(given.trim()).unwrap_or_default()
+                };
+                let same = given_bytes.len() == digest.len()
+                    && given_bytes.iter().zip(&digest).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0;
+                same.to_string()
+            }
+            None if base64 => general_purpose::STANDARD.encode(digest),
+            None => hex::encode(digest),
+        };
+        self.handle_set_as_multiple(i, tokens, vec![result]);
+    }
+
+    fn handle_bit_password(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        // Syntax:
+        // bit password hash "Password" & set as {Hash}
+        // bit password check "Password" with {Hash} & set as {Ok}
+        *i += 1; // Skip "password"
+        if *i + 1 >= tokens.len() {
+            return;
+        }
+        let action = tokens[*i].token_type.clone();
+        *i += 1;
+        let password = self.bit_value(i, tokens);
+
+        let result = match action {
+            TokenType::Check => {
+                if *i + 2 < tokens.len() && tokens[*i + 1].token_type == TokenType::With {
+                    *i += 2;
+                    let stored = self.bit_value(i, tokens);
+                    match PasswordHash::new(stored.trim()) {
+                        // verify_password compares in constant time
+                        Ok(parsed) => Argon2::default()
+                            .verify_password(password.as_bytes(), &parsed)
+                            .is_ok()
+                            .to_string(),
+                        Err(e) => {
+                            self.bit_bug(i, tokens, format!("Stored password hash is not a PHC string. {}", e));
+                            String::from("false")
+                        }
+                    }
+                } else {
+                    println!("Big Error: Expected 'with {{Hash}}' after 'bit password check'.");
+                    return;
+                }
+            }
+            _ => {
+                let salt = SaltString::generate(&mut OsRng);
+                match Argon2::default().hash_password(password.as_bytes(), &salt) {
+                    Ok(hash) => hash.to_string(),
+                    Err(e) => {
+                        self.bit_bug(i, tokens, format!("Password hashing failed. {}", e));
+                        String::new()
+                    }
+                }
+            }
+        };
+        self.handle_set_as_multiple(i, tokens, vec![result]);
+    }
+
+    fn handle_bit_token(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        // Syntax: bit token 32 [alnum | hex | digits | base64url | "CustomAlphabet"] & set as {Token}
+        *i += 1; // Skip "token"
+        if *i >= tokens.len() {
+            return;
+        }
+        let length = self.get_token_value(&tokens[*i]).parse::<usize>().unwrap_or(32);
+
+        let mut alphabet = String::from(ALNUM);
+        if *i + 1 < tokens.len() && tokens[*i + 1].line == tokens[*i].line && tokens[*i + 1].token_type != TokenType::Ampersand {
+            *i += 1;
+            alphabet = match &tokens[*i].token_type {
+                TokenType::Identifier(w) => match w.to_lowercase().as_str() {
+                    "hex" => String::from("0123456789abcdef"),
+                    "digits" => String::from("0123456789"),
+                    "base64url" => format!("{}-_", ALNUM),
+                    _ => String::from(ALNUM),
+                },
+                _ => self.bit_value(i, tokens),
+            };
+        }
+
+        let chars: Vec<char> = alphabet.chars().collect();
+        if chars.is_empty() {
+            println!("Big Error: 'bit token' needs a non-empty alphabet.");
+            return;
+        }
+        // OsRng with gen_range: unpredictable and without modulo bias
+        let token: String = (0..length).map(|_| chars[OsRng.gen_range(0..chars.len())]).collect();
+        self.handle_set_as_multiple(i, tokens, vec![token]);
+    }
+
+    fn handle_bit_keypair(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        // Syntax: bit keypair [@"keys/app"] [base64] & set as {Private} {Public}
+        // With a path, writes keys/app.key (private) and keys/app.pub (public).
+        let mut path = None;
+        let mut raw = false;
+        let line = tokens[*i].line;
+        while *i + 1 < tokens.len() && tokens[*i + 1].line == line && tokens[*i + 1].token_type != TokenType::Ampersand {
+            *i += 1;
+            match &tokens[*i].token_type {
+                TokenType::At => {
+                    if !self.validate_at_strictness(*i, tokens) || *i + 1 >= tokens.len() {
+                        return;
+                    }
+                    *i += 1;
+                    path = Some(self.bit_value(i, tokens));
+                }
+                TokenType::Identifier(w) if w.eq_ignore_ascii_case("base64") => raw = true,
+                _ => {}
+            }
+        }
+
+        let (private, public) = match generate_keypair(raw) {
+            Ok(pair) => pair,
+            Err(e) => {
+                self.bit_bug(i, tokens, e);
+                return;
+            }
+        };
+        if let Some(base) = path {
+            for (ext, text) in [("key", &private), ("pub", &public)] {
+                let file = format!("{}.{}", base, ext);
+                let written = if ext == "key" {
+                    write_private_file(&file, text)
+                } else {
+                    std::fs::write(&file, text)
+                };
+                if let Err(e) = written {
+                    self.bit_bug(i, tokens, format!("Could not write '{}'. {}", file, e));
+                    return;
+                }
+            }
+        }
+        self.handle_set_as_multiple(i, tokens, vec![private, public]);
+    }
+
+    fn handle_bit_sign(&mut self, i: &mut usize, tokens: &Vec<Token>, verify: bool) {
+        // Syntax:
+        // bit sign [file] "Data" with @"app.key" | {PrivateKey} & set as {Signature}
+        // bit verify [file] "Data" with @"app.pub" | {PublicKey} check {Signature} & set as {Ok}
+        *i += 1; // Skip "sign" / "verify"
+        if *i >= tokens.len() {
+            return;
+        }
+        let from_file = tokens[*i].token_type == TokenType::File;
+        if from_file {
+            *i += 1;
+        }
+        let data_raw = self.bit_value(i, tokens);
+        let data = if from_file {
+            match std::fs::read(&data_raw) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    self.bit_bug(i, tokens, format!("Could not read '{}'. {}", data_raw, e));
+                    return;
+                }
+            }
+        } else {
+            data_raw.into_bytes()
+        };
+
+        let mut key_text = None;
+        let mut signature = String::new();
+        let line = tokens[*i].line;
+        while *i + 1 < tokens.len() && tokens[*i + 1].line == line && tokens[*i + 1].token_type != TokenType::Ampersand {
+            *i += 1;
+            match tokens[*i].token_type {
+                TokenType::With if *i + 1 < tokens.len() => {
+                    *i += 1;
+                    key_text = self.read_bit_key(i, tokens);
+                    if key_text.is_none() {
+                        return;
+                    }
+                }
+                TokenType::Check if *i + 1 < tokens.len() => {
+                    *i += 1;
+                    signature = self.bit_value(i, tokens);
+                }
+                _ => {}
+            }
+        }
+
+        let Some(key_text) = key_text else {
+            println!("Big Error: 'bit {}' needs 'with @\"keyfile\"' or 'with {{Key}}'.", if verify { "verify" } else { "sign" });
+            return;
+        };
+        let result = if verify {
+            verify_bytes(&key_text, &data, &signature).map(|ok| ok.to_string())
+        } else {
+            sign_bytes(&key_text, &data)
+        };
+        match result {
+            Ok(value) => self.handle_set_as_multiple(i, tokens, vec![value]),
+            Err(e) => self.bit_bug(i, tokens, e),
+        }
+    }
+
+    // The key after 'with': @"file" is read from disk, anything else is the key itself.
+    // None when the file can't be used (already reported).
+    fn read_bit_key(&mut self, i: &mut usize, tokens: &Vec<Token>) -> Option<String> {
+        if tokens[*i].token_type == TokenType::At {
+            if !self.validate_at_strictness(*i, tokens) || *i + 1 >= tokens.len() {
+                return None;
+            }
+            *i += 1;
+            let file = self.bit_value(i, tokens);
+            return match std::fs::read_to_string(&file) {
+                Ok(text) => Some(text),
+                Err(e) => {
+                    self.bit_bug(i, tokens, format!("Could not read key '{}'. {}", file, e));
+                    None
+                }
+            };
+        }
+        Some(self.bit_value(i, tokens))
+    }
+
+    fn handle_bit_jwt(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        // Syntax:
+        // bit jwt create {Claims} with "Secret" [hs256] [for 15 mins] & set as {Token}
+        // bit jwt create {Claims} with @"app.key" eddsa & set as {Token}
+        // bit jwt verify {Token} with "Secret" | @"app.pub" [hs256 | eddsa] [issuer "X"] [audience "Y"] & set as {Claims}
+        *i += 1; // Skip "jwt"
+        if *i + 1 >= tokens.len() {
+            return;
+        }
+        let verify = matches!(&tokens[*i].token_type, TokenType::Identifier(w) if w.eq_ignore_ascii_case("verify"));
+        *i += 1;
+        let subject = self.bit_value(i, tokens);
+
+        let mut key = String::new();
+        let mut alg = "HS256";
+        let mut lifetime = None;
+        let mut issuer = None;
+        let mut audience = None;
+        let line = tokens[*i].line;
+        while *i + 1 < tokens.len() && tokens[*i + 1].line == line && tokens[*i + 1].token_type != TokenType::Ampersand {
+            *i += 1;
+            match &tokens[*i].token_type {
+                TokenType::With if *i + 1 < tokens.len() => {
+                    *i += 1;
+                    match self.read_bit_key(i, tokens) {
+                        Some(text) => key = text,
+                        None => return,
+                    }
+                }
+                TokenType::For if *i + 2 < tokens.len() => {
+                    let amount = self.get_token_value(&tokens[*i + 1]).parse::<f64>().unwrap_or(0.0);
+                    let unit_secs = match &tokens[*i + 2].token_type {
+                        TokenType::Mins => 60.0,
+                        TokenType::Identifier(unit) => match unit.to_lowercase().as_str() {
+                            "sec" | "secs" | "second" | "seconds" => 1.0,
+                            "hour" | "hours" => 3600.0,
+                            "day" | "days" => 86400.0,
+                            _ => 60.0,
+                        },
+                        _ => 60.0,
+                    };
+                    lifetime = Some((amount * unit_secs).max(0.0) as u64);
+                    *i += 2;
+                }
+                TokenType::Identifier(w) => match w.to_lowercase().as_str() {
+                    "hs256" => alg = "HS256",
+                    "eddsa" => alg = "EdDSA",
+                    "issuer" if *i + 1 < tokens.len() => {
+                        *i += 1;
+                        issuer = Some(self.bit_value(i, tokens));
+                    }
+                    "audience" if *i + 1 < tokens.len() => {
+                        *i += 1;
+                        audience = Some(self.bit_value(i, tokens));
+                    }
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        let result = if verify {
+            jwt_verify(&subject, alg, &key, issuer.as_deref(), audience.as_deref())
+        } else {
+            jwt_create(&subject, alg, &key, lifetime)
+        };
+        match result {
+            Ok(value) if verify => self.set_json_result(i, tokens, value),
+            Ok(value) => self.handle_set_as_multiple(i, tokens, vec![value]),
+            Err(e) => self.bit_bug(i, tokens, format!("JWT {}", e)),
+        }
+    }
+
+    // Security failures (tampering, bad tokens) are bugs: they halt unless the script checks 'if any bug found'.
+    // The rest of the line (e.g. '& set as') is skipped so the check on the next line can catch it.
+    fn bit_bug(&mut self, i: &mut usize, tokens: &Vec<Token>, message: String) {
+        let line = tokens[*i].line;
+        while *i + 1 < tokens.len() && tokens[*i + 1].line == line {
+            *i += 1;
+        }
+        println!("Big Error: {}", message);
+        self.last_bug_found = true;
+        self.last_bug_type = format!("Bit Error: {}", message);
+        self.set_variable("BugType".to_string(), self.last_bug_type.clone());
+    }
+
     fn holmes_math_cipher(&self, text: &str, key: &str, encrypt: bool) -> String {
         if encrypt {
             let mut rng = BigRng::new(key);
@@ -193,6 +643,445 @@ impl Interpreter {
     }
 }
 
+const ALNUM: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+
+// --- ENCODINGS ---
+pub fn encode_text(text: &str, format: &str) -> Result<String, String> {
+    let bytes = text.as_bytes();
+    Ok(match format {
+        "base64" => general_purpose::STANDARD.encode(bytes),
+        "base64url" => general_purpose::URL_SAFE_NO_PAD.encode(bytes),
+        "base32" => BASE32.encode(bytes),
+        "hex" => hex::encode(bytes),
+        "url" => utf8_percent_encode(text, URL_SAFE).to_string(),
+        "html" => escape_html(text),
+        other => return Err(unknown_encoding(other)),
+    })
+}
+
+pub fn decode_text(text: &str, format: &str) -> Result<String, String> {
+    let text = text.trim();
+    let bytes = match format {
+        // Accept padded and unpadded input alike
+        "base64" => general_purpose::STANDARD_NO_PAD.decode(text.trim_end_matches('=')).ok(),
+        "base64url" => general_purpose::URL_SAFE_NO_PAD.decode(text.trim_end_matches('=')).ok(),
+        "base32" => BASE32.decode(text.to_uppercase().as_bytes()).ok(),
+        "hex" => hex::decode(text).ok(),
+        "url" => Some(percent_decode_str(&text.replace('+', " ")).collect()),
+        "html" => return Ok(unescape_html(text)),
+        other => return Err(unknown_encoding(other)),
+    };
+    let bytes = bytes.ok_or_else(|| format!("Decode failed: not valid {}.", format))?;
+    String::from_utf8(bytes).map_err(|_| format!("Decode failed: the {} data is not UTF-8 text.", format))
+}
+
+fn unknown_encoding(format: &str) -> String {
+    format!("Unknown encoding '{}'. Use base64, base64url, base32, hex, url or html.", format)
+}
+
+// Everything except the RFC 3986 unreserved characters ( A-Z a-z 0-9 - . _ ~ ) is escaped
+const URL_SAFE: &percent_encoding::AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// Named entities for the escaped characters (plus &nbsp;) and any numeric &#..; / &#x..; entity.
+fn unescape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|end| *end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some('\u{a0}'),
+                _ => {
+                    let num = entity.strip_prefix('#')?;
+                    let code = match num.strip_prefix('x').or_else(|| num.strip_prefix('X')) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => num.parse::<u32>().ok()?,
+                    };
+                    char::from_u32(code)
+                }
+            }?;
+            Some((c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// --- AES-GCM BLOBS ---
+// bgcm1.argon2id.m=19456,t=2,p=1.<salt>.<nonce>.<ciphertext+tag>   (base64, no padding)
+// The key is derived from the passphrase with Argon2id and a random salt; every seal gets a fresh nonce.
+// The KDF settings travel in the blob, so older blobs still open if the defaults change.
+const GCM_TAG: &str = "bgcm1";
+// Highest KDF settings a blob may ask for (memory in KiB, passes, lanes). The blob is untrusted
+// input, so this stops a crafted one from making 'open' allocate gigabytes or spin for minutes.
+const GCM_MAX_COST: [u32; 3] = [64 * 1024, 8, 4];
+
+fn derive_gcm_key(pass: &[u8], salt: &[u8], params: Params) -> Result<[u8; 32], String> {
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(pass, salt, &mut key)
+        .map_err(|e| format!("Key derivation failed. {}", e))?;
+    Ok(key)
+}
+
+pub fn gcm_seal(plain: &str, pass: &[u8]) -> Result<String, String> {
+    let params = Params::default();
+    let mut salt = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let key = derive_gcm_key(pass, &salt, params.clone())?;
+    let cipher = Aes256Gcm::new(&key.into());
+    let sealed = cipher
+        .encrypt(Nonce::from_slice(&nonce), plain.as_bytes())
+        .map_err(|_| String::from("AES-GCM encryption failed."))?;
+
+    let b64 = general_purpose::STANDARD_NO_PAD;
+    Ok(format!(
+        "{}.argon2id.m={},t={},p={}.{}.{}.{}",
+        GCM_TAG,
+        params.m_cost(),
+        params.t_cost(),
+        params.p_cost(),
+        b64.encode(salt),
+        b64.encode(nonce),
+        b64.encode(sealed)
+    ))
+}
+
+pub fn gcm_open(blob: &str, pass: &[u8]) -> Result<String, String> {
+    let parts: Vec<&str> = blob.trim().split('.').collect();
+    if parts.len() != 6 || parts[0] != GCM_TAG || parts[1] != "argon2id" {
+        return Err(String::from("Not an AES-GCM blob from 'bit aes encrypt ... with'."));
+    }
+
+    let mut cost = [0u32; 3];
+    for setting in parts[2].split(',') {
+        let (name, val) = setting.split_once('=').unwrap_or(("", ""));
+        let val = val.parse::<u32>().unwrap_or(0);
+        match name {
+            "m" => cost[0] = val,
+            "t" => cost[1] = val,
+            "p" => cost[2] = val,
+            _ => {}
+        }
+    }
+    if cost.iter().zip(GCM_MAX_COST).any(|(val, max)| *val > max) {
+        return Err(format!(
+            "KDF settings in blob are above the limit (m={}, t={}, p={}).",
+            GCM_MAX_COST[0], GCM_MAX_COST[1], GCM_MAX_COST[2]
+        ));
+    }
+    let params = Params::new(cost[0], cost[1], cost[2], None).map_err(|e| format!("Bad KDF settings in blob. {}", e))?;
+
+    let b64 = general_purpose::STANDARD_NO_PAD;
+    let decode = |part: &str| b64.decode(part).map_err(|_| String::from("AES-GCM blob is not valid base64."));
+    let salt = decode(parts[3])?;
+    let nonce = decode(parts[4])?;
+    let sealed = decode(parts[5])?;
+    if nonce.len() != 12 {
+        return Err(String::from("AES-GCM blob has a bad nonce."));
+    }
+
+    let key = derive_gcm_key(pass, &salt, params)?;
+    let cipher = Aes256Gcm::new(&key.into());
+    let plain = cipher
+        .decrypt(Nonce::from_slice(&nonce), sealed.as_ref())
+        .map_err(|_| String::from("AES-GCM check failed: wrong passphrase or the data was tampered with."))?;
+    String::from_utf8(plain).map_err(|_| String::from("Decrypted data is not valid UTF-8."))
+}
+
+// Like fs::write, but only the owner may read the file (private keys).
+fn write_private_file(path: &str, text: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut f = options.open(path)?;
+    // An existing file keeps its old mode on open, so set it again
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        f.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    std::io::Write::write_all(&mut f, text.as_bytes())
+}
+
+// --- ED25519 ---
+// Keys are PEM (PKCS#8 private / SPKI public) or the raw 32 bytes in base64. Signatures are base64.
+pub fn generate_keypair(raw: bool) -> Result<(String, String), String> {
+    let key = SigningKey::generate(&mut OsRng);
+    if raw {
+        let b64 = general_purpose::STANDARD;
+        return Ok((b64.encode(key.to_bytes()), b64.encode(key.verifying_key().to_bytes())));
+    }
+    let private = key
+        .to_pkcs8_pem(LineEnding::LF)
+        .map_err(|e| format!("Could not encode the private key. {}", e))?;
+    let public = key
+        .verifying_key()
+        .to_public_key_pem(LineEnding::LF)
+        .map_err(|e| format!("Could not encode the public key. {}", e))?;
+    Ok((private.to_string(), public))
+}
+
+fn raw_key_bytes(text: &str) -> Result<[u8; 32], String> {
+    general_purpose::STANDARD
+        .decode(text.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| String::from("Key is neither PEM nor 32 bytes of base64."))
+}
+
+pub fn load_signing_key(text: &str) -> Result<SigningKey, String> {
+    if text.contains("-----BEGIN") {
+        SigningKey::from_pkcs8_pem(text.trim()).map_err(|e| format!("Bad private key PEM. {}", e))
+    } else {
+        Ok(SigningKey::from_bytes(&raw_key_bytes(text)?))
+    }
+}
+
+pub fn load_verifying_key(text: &str) -> Result<VerifyingKey, String> {
+    if text.contains("-----BEGIN") {
+        VerifyingKey::from_public_key_pem(text.trim()).map_err(|e| format!("Bad public key PEM. {}", e))
+    } else {
+        VerifyingKey::from_bytes(&raw_key_bytes(text)?).map_err(|e| format!("Bad public key. {}", e))
+    }
+}
+
+pub fn sign_bytes(private_key: &str, data: &[u8]) -> Result<String, String> {
+    let key = load_signing_key(private_key)?;
+    Ok(general_purpose::STANDARD.encode(key.sign(data).to_bytes()))
+}
+
+// Ok(false) for a wrong or malformed signature; Err only when the key itself is unusable.
+pub fn verify_bytes(public_key: &str, data: &[u8], signature_b64: &str) -> Result<bool, String> {
+    let key = load_verifying_key(public_key)?;
+    let Some(bytes) = general_purpose::STANDARD
+        .decode(signature_b64.trim())
+        .ok()
+        .and_then(|b| <[u8; 64]>::try_from(b).ok())
+    else {
+        return Ok(false);
+    };
+    Ok(key.verify(data, &Signature::from_bytes(&bytes)).is_ok())
+}
+
+// --- JWT ---
+// Compact JWS: base64url(header).base64url(claims).base64url(signature), HS256 or EdDSA.
+fn jwt_signature(alg: &str, key: &str, signing_input: &[u8]) -> Result<Vec<u8>, String> {
+    match alg {
+        "EdDSA" => Ok(load_signing_key(key)?.sign(signing_input).to_bytes().to_vec()),
+        "HS256" => {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key.as_bytes()).map_err(|_| String::from("secret is unusable."))?;
+            mac.update(signing_input);
+            Ok(mac.finalize().into_bytes().to_vec())
+        }
+        other => Err(format!("algorithm {} is not supported.", other)),
+    }
+}
+
+pub fn jwt_create(claims: &str, alg: &str, key: &str, lifetime: Option<u64>) -> Result<String, String> {
+    if key.is_empty() {
+        return Err(String::from("needs a key: 'with \"Secret\"' or 'with @\"app.key\"'."));
+    }
+    let Ok(serde_json::Value::Object(mut map)) = serde_json::from_str::<serde_json::Value>(claims) else {
+        return Err(String::from("claims must be a map."));
+    };
+    // BigC maps hold numbers as text; the time claims must be JSON numbers
+    for name in ["exp", "nbf", "iat"] {
+        if let Some(n) = map.get(name).and_then(claim_number) {
+            map.insert(name.to_string(), serde_json::Value::from(n));
+        }
+    }
+    let now = super::dbig::unix_now();
+    map.entry("iat").or_insert(serde_json::Value::from(now));
+    if let Some(secs) = lifetime {
+        map.insert("exp".to_string(), serde_json::Value::from(now + secs));
+    }
+
+    let b64 = general_purpose::URL_SAFE_NO_PAD;
+    let header = serde_json::json!({ "alg": alg, "typ": "JWT" });
+    let signing_input = format!("{}.{}", b64.encode(header.to_string()), b64.encode(serde_json::Value::Object(map).to_string()));
+    let signature = jwt_signature(alg, key, signing_input.as_bytes())?;
+    Ok(format!("{}.{}", signing_input, b64.encode(signature)))
+}
+
+// Returns the claims as JSON, or the reason the token was refused.
+pub fn jwt_verify(token: &str, alg: &str, key: &str, issuer: Option<&str>, audience: Option<&str>) -> Result<String, String> {
+    if key.is_empty() {
+        return Err(String::from("needs a key: 'with \"Secret\"' or 'with @\"app.pub\"'."));
+    }
+    let b64 = general_purpose::URL_SAFE_NO_PAD;
+    let parts: Vec<&str> = token.trim().split('.').collect();
+    if parts.len() != 3 {
+        return Err(String::from("is malformed: expected header.claims.signature."));
+    }
+    let decode_json = |part: &str| -> Option<serde_json::Value> { serde_json::from_slice(&b64.decode(part).ok()?).ok() };
+    let header = decode_json(parts[0]).ok_or("has an unreadable header.")?;
+    let claims = decode_json(parts[1]).ok_or("has unreadable claims.")?;
+
+    // The algorithm is fixed by the caller, never taken from the token
+    if header["alg"].as_str() != Some(alg) {
+        return Err(format!("algorithm is {} but {} was expected.", header["alg"], alg));
+    }
+    let signature = b64.decode(parts[2]).map_err(|_| String::from("has an unreadable signature."))?;
+    let signing_input = format!("{}.{}", parts[0], parts[1]);
+    let valid = match alg {
+        "EdDSA" => {
+            let sig_b64 = general_purpose::STANDARD.encode(&signature);
+            verify_bytes(key, signing_input.as_bytes(), &sig_b64)?
+        }
+        "HS256" => {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key.as_bytes()).map_err(|_| String::from("secret is unusable."))?;
+            mac.update(signing_input.as_bytes());
+            mac.verify_slice(&signature).is_ok()
+        }
+        other => return Err(format!("algorithm {} is not supported.", other)),
+    };
+    if !valid {
+        return Err(String::from("signature is invalid."));
+    }
+
+    let now = super::dbig::unix_now();
+    if let Some(exp) = time_claim(&claims, "exp")? {
+        if now >= exp {
+            return Err(format!("has expired (exp {}, now {}).", exp, now));
+        }
+    }
+    if let Some(nbf) = time_claim(&claims, "nbf")? {
+        if now < nbf {
+            return Err(format!("is not valid yet (nbf {}, now {}).", nbf, now));
+        }
+    }
+    if let Some(expected) = issuer {
+        if claims["iss"].as_str() != Some(expected) {
+            return Err(format!("issuer is {} but '{}' was expected.", claims["iss"], expected));
+        }
+    }
+    if let Some(expected) = audience {
+        let matches = match &claims["aud"] {
+            serde_json::Value::String(a) => a == expected,
+            serde_json::Value::Array(list) => list.iter().any(|a| a.as_str() == Some(expected)),
+            _ => false,
+        };
+        if !matches {
+            return Err(format!("audience {} does not include '{}'.", claims["aud"], expected));
+        }
+    }
+    Ok(claims.to_string())
+}
+
+// A time claim in a token must be a JSON number (seconds). Anything else makes the token invalid,
+// so a claim that can't be read is never just skipped.
+fn time_claim(claims: &serde_json::Value, name: &str) -> Result<Option<u64>, String> {
+    match claims.get(name) {
+        None => Ok(None),
+        Some(serde_json::Value::Number(n)) => n
+            .as_u64()
+            .or_else(|| n.as_f64().filter(|f| *f >= 0.0).map(|f| f as u64))
+            .map(Some)
+            .ok_or(format!("has a negative '{}' claim.", name)),
+        Some(other) => Err(format!("'{}' claim is not a number ({}).", name, other)),
+    }
+}
+
+fn claim_number(val: &serde_json::Value) -> Option<u64> {
+    match val {
+        serde_json::Value::Number(n) => n.as_u64().or_else(|| n.as_f64().map(|f| f as u64)),
+        serde_json::Value::String(s) => s.trim().parse::<f64>().ok().map(|f| f as u64),
+        _ => None,
+    }
+}
+
+// Hashes everything 'input' yields. With a key it is an HMAC (blake3 uses its keyed mode).
+fn digest_reader(algo: &str, key: Option<&[u8]>, mut input: Box<dyn Read>) -> Result<Vec<u8>, String> {
+    fn feed(input: &mut Box<dyn Read>, mut update: impl FnMut(&[u8])) -> Result<(), String> {
+        let mut buf = [0u8; 64 * 1024];
+        loop {
+            match input.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) => update(&buf[..n]),
+                Err(e) => return Err(format!("Could not read the input. {}", e)),
+            }
+        }
+    }
+
+    match (algo, key) {
+        ("sha256", None) => {
+            let mut h = Sha256::new();
+            feed(&mut input, |b| h.update(b))?;
+            Ok(h.finalize().to_vec())
+        }
+        ("sha512", None) => {
+            let mut h = Sha512::new();
+            feed(&mut input, |b| h.update(b))?;
+            Ok(h.finalize().to_vec())
+        }
+        ("blake3", None) => {
+            let mut h = blake3::Hasher::new();
+            feed(&mut input, |b| {
+                h.update(b);
+            })?;
+            Ok(h.finalize().as_bytes().to_vec())
+        }
+        ("sha256", Some(k)) => {
+            let mut m = <Hmac<Sha256> as Mac>::new_from_slice(k).map_err(|_| String::from("HMAC key is not usable."))?;
+            feed(&mut input, |b| m.update(b))?;
+            Ok(m.finalize().into_bytes().to_vec())
+        }
+        ("sha512", Some(k)) => {
+            let mut m = <Hmac<Sha512> as Mac>::new_from_slice(k).map_err(|_| String::from("HMAC key is not usable."))?;
+            feed(&mut input, |b| m.update(b))?;
+            Ok(m.finalize().into_bytes().to_vec())
+        }
+        ("blake3", Some(k)) => {
+            let mut h = blake3::Hasher::new_keyed(&blake3::hash(k).into());
+            feed(&mut input, |b| {
+                h.update(b);
+            })?;
+            Ok(h.finalize().as_bytes().to_vec())
+        }
+        (other, _) => Err(format!("Unknown hash '{}'. Use sha256, sha512 or blake3.", other)),
+    }
+}
+
 // Simple deterministic PRNG for NEBC Stream Cipher (PCG-like)
 struct BigRng {
     state: u64,
@@ -216,3 +1105,69 @@ impl BigRng {
         ((self.state ^ (self.state >> 18)) >> 27) as u8
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gcm_round_trip_and_tamper_rejection() {
+        let blob = gcm_seal("secret $text", b"pass phrase").unwrap();
+        assert!(blob.starts_with("bgcm1.argon2id."));
+        assert_eq!(gcm_open(&blob, b"pass phrase").unwrap(), "secret $text");
+        // Every seal gets its own salt and nonce
+        assert_ne!(gcm_seal("secret $text", b"pass phrase").unwrap(), blob);
+
+        assert!(gcm_open(&blob, b"wrong").is_err());
+
+        let mut parts: Vec<String> = blob.split('.').map(String::from).collect();
+        let first = if parts[5].starts_with('A') { "B" } else { "A" };
+        parts[5].replace_range(0..1, first);
+        let tampered = parts.join(".");
+        let err = gcm_open(&tampered, b"pass phrase").unwrap_err();
+        assert!(err.contains("tampered"), "{}", err);
+
+        assert!(gcm_open("bgcm1.argon2id.m=1,t=1,p=1.AA.AA", b"pass phrase").is_err());
+    }
+
+    #[test]
+    fn gcm_refuses_blobs_above_the_kdf_cap() {
+        // Refused before any key derivation, so a crafted blob costs nothing to reject
+        for cost in ["m=4194304,t=1,p=1", "m=19456,t=100,p=1", "m=19456,t=2,p=64"] {
+            let blob = format!("bgcm1.argon2id.{}.AAAAAAAAAAAAAAAAAAAAAA.AAAAAAAAAAAAAAAA.AAAA", cost);
+            let err = gcm_open(&blob, b"pw").unwrap_err();
+            assert!(err.contains("above the limit"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn jwt_verify_refuses_bad_tokens() {
+        let secret = "top secret";
+        let token = jwt_create(r#"{"sub":"ana","iss":"big","aud":["web","app"]}"#, "HS256", secret, Some(60)).unwrap();
+        let claims = jwt_verify(&token, "HS256", secret, Some("big"), Some("app")).unwrap();
+        assert!(claims.contains(r#""sub":"ana""#), "{}", claims);
+
+        let refused = |token: &str, alg: &str, key: &str, iss: Option<&str>, aud: Option<&str>, why: &str| {
+            let err = jwt_verify(token, alg, key, iss, aud).unwrap_err();
+            assert!(err.contains(why), "expected '{}', got '{}'", why, err);
+        };
+        refused(&token, "HS256", "", None, None, "needs a key");
+        refused("a.b", "HS256", secret, None, None, "is malformed");
+        refused(&token, "HS256", "other secret", None, None, "signature is invalid");
+        refused(&token, "EdDSA", secret, None, None, "but EdDSA was expected");
+        refused(&token, "HS256", secret, Some("someone"), None, "issuer");
+        refused(&token, "HS256", secret, None, Some("mobile"), "audience");
+
+        // An unsigned token never verifies, even when the caller names its algorithm
+        let b64 = general_purpose::URL_SAFE_NO_PAD;
+        let unsigned = format!("{}.{}.", b64.encode(r#"{"alg":"none"}"#), b64.encode(r#"{"sub":"ana"}"#));
+        refused(&unsigned, "none", secret, None, None, "not supported");
+
+        let expired = jwt_create(r#"{"exp":"1"}"#, "HS256", secret, None).unwrap();
+        refused(&expired, "HS256", secret, None, None, "has expired");
+        let early = jwt_create(&format!(r#"{{"nbf":{}}}"#, super::super::dbig::unix_now() + 3600), "HS256", secret, None).unwrap();
+        refused(&early, "HS256", secret, None, None, "is not valid yet");
+        let unreadable = jwt_create(r#"{"exp":"soon"}"#, "HS256", secret, None).unwrap();
+        refused(&unreadable, "HS256", secret, None, None, "'exp' claim is not a number");
+    }
+}
@@ -318,6 +318,20 @@ impl Interpreter {
                         if !is_sloop {
                             *i += 1;
                         }
+                        // Nothing to walk (e.g. an empty SQL result): skip the body and its 'keep'
+                        if is_foreach && foreach_list.is_empty() && foreach_keys.is_empty() {
+                            while *i < tokens.len() && tokens[*i].column > tokens[start_idx].column {
+                                *i += 1;
+                            }
+                            if *i < tokens.len() && tokens[*i].token_type == TokenType::Keep {
+                                let line = tokens[*i].line;
+                                while *i < tokens.len() && tokens[*i].line == line {
+                                    *i += 1;
+                                }
+                            }
+                            *i -= 1; // Main loop will increment
+                            return;
+                        }
                         let mut valid = false;
                         if *i < tokens.len() && tokens[*i].column > tokens[start_idx].column {
                             valid = true;
@@ -371,6 +385,8 @@ impl Interpreter {
                                 *i = loop_info.start_index;
                                 *i -= 1;
                                 self.loop_stack.push(loop_info);
+                            } else {
+                                *i -= 1; // Done: let the statement after 'keep' run
                             }
                             return;
                         }
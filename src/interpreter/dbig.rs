@@ -1,16 +1,293 @@
 use super::Interpreter;
 use crate::tokens::{Token, TokenType};
+use regex::Regex;
 use serde_json::Value;
+use std::collections::HashMap;
 use std::fs::{self};
 use std::io::Write;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+// --- STORAGE ENGINE ---
+// A parsed .dbig file: blocks in file order plus an index by key.
+// The text format is unchanged:  [Key]  followed by  - value |  lines.
+// A key set with 'for N mins' also gets an  @expires <unix seconds>  line under its header.
+// Any other line (comments, notes) is kept with the block below it, so a rewrite never drops it.
+#[derive(Debug, Clone, Default)]
+pub struct DbigTable {
+    entries: Vec<(String, Vec<String>)>,
+    index: HashMap<String, usize>,
+    expires: HashMap<String, u64>,
+    notes: HashMap<String, Vec<String>>, // Unknown lines written above a block
+    trailer: Vec<String>,                // Unknown lines after the last block
+}
+
+impl DbigTable {
+    pub fn parse(content: &str) -> Self {
+        let mut table = DbigTable::default();
+        let mut current: Option<usize> = None;
+        let mut pending: Vec<String> = Vec::new();
+        for line in content.lines() {
+            let trim = line.trim();
+            if let Some(key) = block_key(trim) {
+                // A repeated key continues its first block, like the old line scanner did
+                let pos = match table.index.get(key) {
+                    Some(pos) => *pos,
+                    None => {
+                        table.entries.push((key.to_string(), Vec::new()));
+                        table.index.insert(key.to_string(), table.entries.len() - 1);
+                        table.entries.len() - 1
+                    }
+                };
+                if !pending.is_empty() {
+                    table.notes.entry(key.to_string()).or_default().append(&mut pending);
+                }
+                current = Some(pos);
+            } else if let (Some(pos), Some(val)) = (current, block_value(trim)) {
+                table.entries[pos].1.push(val.to_string());
+            } else if let (Some(pos), Some(at)) = (current, block_expiry(trim)) {
+                table.expires.insert(table.entries[pos].0.clone(), at);
+            } else if !trim.is_empty() {
+                pending.push(line.trim_end().to_string());
+            }
+        }
+        table.trailer = pending;
+        table
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Vec<String>> {
+        self.index.get(key).map(|pos| &self.entries[*pos].1)
+    }
+
+    pub fn set(&mut self, key: &str, values: Vec<String>) {
+        self.expires.remove(key);
+        match self.index.get(key) {
+            Some(pos) => self.entries[*pos].1 = values,
+            None => {
+                self.entries.push((key.to_string(), values));
+                self.index.insert(key.to_string(), self.entries.len() - 1);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.expires.remove(key);
+        let Some(pos) = self.index.remove(key) else {
+            return false;
+        };
+        self.entries.remove(pos);
+        for (k, _) in &self.entries[pos..] {
+            if let Some(p) = self.index.get_mut(k) {
+                *p -= 1;
+            }
+        }
+        // The block's notes move on to whatever follows it
+        if let Some(mut notes) = self.notes.remove(key) {
+            let next = match self.entries.get(pos) {
+                Some((next, _)) => self.notes.entry(next.clone()).or_default(),
+                None => &mut self.trailer,
+            };
+            notes.append(next);
+            *next = notes;
+        }
+        true
+    }
+
+    pub fn entries(&self) -> &[(String, Vec<String>)] {
+        &self.entries
+    }
+
+    // Unix time (seconds) after which 'key' counts as absent.
+    pub fn expires_at(&self, key: &str) -> Option<u64> {
+        self.expires.get(key).copied()
+    }
+
+    pub fn expire(&mut self, key: &str, at: u64) {
+        if self.index.contains_key(key) {
+            self.expires.insert(key.to_string(), at);
+        }
+    }
+
+    pub fn has_expired(&self, now: u64) -> bool {
+        self.expires.values().any(|at| *at <= now)
+    }
+
+    // Drops every expired key. Returns how many were removed.
+    pub fn sweep(&mut self, now: u64) -> usize {
+        let dead: Vec<String> = self
+            .expires
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &dead {
+            self.remove(key);
+        }
+        dead.len()
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for (key, values) in &self.entries {
+            for note in self.notes.get(key).into_iter().flatten() {
+                out.push_str(&format!("{}\n", note));
+            }
+            out.push_str(&format!("[{}]\n", key));
+            if let Some(at) = self.expires.get(key) {
+                out.push_str(&format!("@expires {}\n", at));
+            }
+            for val in values {
+                out.push_str(&format!("- {} |\n", val));
+            }
+            out.push('\n');
+        }
+        for note in &self.trailer {
+            out.push_str(&format!("{}\n", note));
+        }
+        out
+    }
+}
+
+// One pending write inside a 'dbig begin' batch.
+#[derive(Debug, Clone)]
+pub enum DbigOp {
+    Set(String, Vec<String>, Option<u64>), // Key, values, expiry
+    Remove(String),
+}
+
+impl DbigOp {
+    pub fn apply(&self, table: &mut DbigTable) {
+        match self {
+            DbigOp::Set(key, values, expires) => {
+                table.set(key, values.clone());
+                if let Some(at) = expires {
+                    table.expire(key, *at);
+                }
+            }
+            DbigOp::Remove(key) => {
+                table.remove(key);
+            }
+        }
+    }
+}
+
+// A table as last read from disk, with the (modified, length) it was read at.
+// A same-length rewrite within one timestamp tick keeps that stamp, so until the file's
+// mtime is STAMP_SETTLE older than 'checked_at' the content hash is compared as well.
+#[derive(Debug, Clone)]
+pub struct DbigCached {
+    stamp: Option<(SystemTime, u64)>,
+    checked_at: SystemTime,
+    hash: blake3::Hash,
+    table: Arc<DbigTable>,
+}
+
+const STAMP_SETTLE: Duration = Duration::from_secs(2);
+
+fn stamp_settled(stamp: Option<(SystemTime, u64)>, checked_at: SystemTime) -> bool {
+    match stamp {
+        Some((modified, _)) => checked_at
+            .duration_since(modified)
+            .is_ok_and(|age| age >= STAMP_SETTLE),
+        None => true,
+    }
+}
+
+fn block_key(trim: &str) -> Option<&str> {
+    if trim.starts_with('[') && trim.ends_with(']') && trim.len() >= 2 {
+        Some(&trim[1..trim.len() - 1])
+    } else {
+        None
+    }
+}
+
+fn block_value(trim: &str) -> Option<&str> {
+    if trim.len() >= 4 && trim.starts_with("- ") && trim.ends_with(" |") {
+        Some(&trim[2..trim.len() - 2])
+    } else {
+        None
+    }
+}
+
+fn block_expiry(trim: &str) -> Option<u64> {
+    trim.strip_prefix("@expires ")?.trim().parse::<u64>().ok()
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn file_stamp(filename: &str) -> Option<(SystemTime, u64)> {
+    let meta = fs::metadata(filename).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+// Cross-process writer lock ('file.dbig.lock'), released on drop.
+// The file holds a token unique to its holder, so a writer only ever removes its own lock.
+struct DbigLock {
+    path: String,
+    token: String,
+}
+
+// A write holds the lock for milliseconds, so a lock this old was left by a writer that crashed.
+const LOCK_STALE: Duration = Duration::from_secs(5);
+const LOCK_WAIT: Duration = Duration::from_secs(6);
+
+impl DbigLock {
+    fn acquire(filename: &str) -> Result<Self, String> {
+        let path = format!("{}.lock", filename);
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let started = std::time::Instant::now();
+        loop {
+            match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut f) => {
+                    // Lock acquired atomically
+                    let _ = f.write_all(token.as_bytes());
+                    return Ok(DbigLock { path, token });
+                }
+                Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => {
+                    return Err(format!("Could not create the lock file '{}'. {}", path, e));
+                }
+                Err(_) => {}
+            }
+            // Checked again right before removing, so a lock just taken over by another writer survives
+            let stale = || {
+                fs::metadata(&path)
+                    .and_then(|m| m.modified())
+                    .is_ok_and(|at| at.elapsed().is_ok_and(|age| age >= LOCK_STALE))
+            };
+            if stale() {
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            if started.elapsed() >= LOCK_WAIT {
+                return Err(format!("'{}' is locked by another writer.", filename));
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for DbigLock {
+    fn drop(&mut self) {
+        if fs::read_to_string(&self.path).is_ok_and(|held| held == self.token) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
 
 impl Interpreter {
     pub fn handle_dbig(&mut self, i: &mut usize, tokens: &Vec<Token>) {
         // Syntax:
         // 1. dbig get "Key" @"file"
-        // 2. dbig set "Key" as "Val" @"file"
+        // 2. dbig set "Key" as "Val" [for 10 mins] @"file"
         // 3. dbig remove "Key" @"file"
         // 4. dbig check ... (Existence, List Search, Scanner)
+        // 5. dbig begin @"file"  /  dbig commit  /  dbig rollback (Batch)
+        // 6. dbig sweep @"file" (Drop expired keys)
 
         *i += 1; // Skip "dbig"
 
@@ -33,6 +310,12 @@ impl Interpreter {
             TokenType::Check => {
                 self.handle_dbig_check(i, tokens);
             }
+            TokenType::Identifier(ref w) if ["begin", "commit", "rollback"].contains(&w.to_lowercase().as_str()) => {
+                self.handle_dbig_batch(i, tokens);
+            }
+            TokenType::Identifier(ref w) if w.eq_ignore_ascii_case("sweep") => {
+                self.handle_dbig_sweep(i, tokens);
+            }
             _ => {}
         }
     }
@@ -56,8 +339,8 @@ impl Interpreter {
                     return;
                 }
 
-                let content = fs::read_to_string(&filename).unwrap_or_default();
-                let found_values = self.parse_dbig_content(&content, &key);
+                let table = self.dbig_view(&filename);
+                let found_values = table.get(&key).cloned().unwrap_or_default();
                 self.handle_set_as_multiple(i, tokens, found_values);
             }
         }
@@ -89,6 +372,25 @@ impl Interpreter {
                 *i += 1;
             }
 
+            // Optional: for 10 mins / seconds / hours / days
+            let mut expires = None;
+            if *i + 2 < tokens.len() && tokens[*i].token_type == TokenType::For {
+                let amount = self.get_token_value(&tokens[*i + 1]).parse::<f64>().unwrap_or(0.0);
+                let unit_secs = match &tokens[*i + 2].token_type {
+                    TokenType::Mins => 60.0,
+                    TokenType::Identifier(unit) => match unit.to_lowercase().as_str() {
+                        "sec" | "secs" | "second" | "seconds" => 1.0,
+                        "min" | "minute" | "minutes" => 60.0,
+                        "hour" | "hours" => 3600.0,
+                        "day" | "days" => 86400.0,
+                        _ => 60.0,
+                    },
+                    _ => 60.0,
+                };
+                expires = Some(unix_now() + (amount * unit_secs).max(0.0) as u64);
+                *i += 3;
+            }
+
             if is_list_explicit || val_interp.starts_with('[') {
                 if let Ok(parsed) = serde_json::from_str::<Value>(&val_interp) {
                     if let Some(arr) = parsed.as_array() {
@@ -124,30 +426,7 @@ impl Interpreter {
                         return;
                     }
 
-                    // --- ATOMIC SPIN-LOCK START ---
-                    let lock_path = format!("{}.lock", filename);
-                    let mut attempts = 0;
-                    while attempts < 100 {
-                        match std::fs::OpenOptions::new()
-                            .write(true)
-                            .create_new(true)
-                            .open(&lock_path)
-                        {
-                            Ok(_) => break, // Lock acquired atomically
-                            Err(_) => {
-                                std::thread::sleep(std::time::Duration::from_millis(10));
-                                attempts += 1;
-                            }
-                        }
-                    }
-                    // --- LOCK ACQUIRED ---
-
-                    let content = fs::read_to_string(&filename).unwrap_or_default();
-                    let new_content = self.update_dbig_content(&content, &key, values_to_write);
-                    let _ = fs::write(&filename, new_content);
-
-                    // --- RELEASE LOCK ---
-                    let _ = std::fs::remove_file(&lock_path);
+                    self.dbig_apply(&filename, DbigOp::Set(key, values_to_write, expires));
                 }
             }
         }
@@ -172,30 +451,7 @@ impl Interpreter {
                     return;
                 }
 
-                // --- ATOMIC SPIN-LOCK START ---
-                let lock_path = format!("{}.lock", filename);
-                let mut attempts = 0;
-                while attempts < 100 {
-                    match std::fs::OpenOptions::new()
-                        .write(true)
-                        .create_new(true)
-                        .open(&lock_path)
-                    {
-                        Ok(_) => break,
-                        Err(_) => {
-                            std::thread::sleep(std::time::Duration::from_millis(10));
-                            attempts += 1;
-                        }
-                    }
-                }
-                // --- LOCK ACQUIRED ---
-
-                let content = fs::read_to_string(&filename).unwrap_or_default();
-                let new_content = self.remove_dbig_block(&content, &key);
-                let _ = fs::write(&filename, new_content);
-
-                // --- RELEASE LOCK ---
-                let _ = std::fs::remove_file(&lock_path);
+                self.dbig_apply(&filename, DbigOp::Remove(key));
             }
         }
     }
@@ -203,7 +459,7 @@ impl Interpreter {
     fn handle_dbig_check(&mut self, i: &mut usize, tokens: &Vec<Token>) {
         // Mode 1: dbig check "Key" @"file.dbig" (Existence)
         // Mode 2: dbig check "Item" of "Key" @"file.dbig" (List Search)
-        // Mode 3: dbig check keys value < 10 @"file.dbig" (Scanner)
+        // Mode 3: dbig check keys|map value < 10 [and ...] @"file.dbig" (Scanner / Query)
 
         *i += 1; // Skip "check"
 
@@ -211,8 +467,8 @@ impl Interpreter {
             return;
         }
 
-        if tokens[*i].token_type == TokenType::Keys {
-            // Mode 3: Scanner
+        if tokens[*i].token_type == TokenType::Keys || tokens[*i].token_type == TokenType::Map {
+            // Mode 3: Scanner / Query
             self.handle_dbig_scan(i, tokens);
             return;
         }
@@ -237,9 +493,10 @@ impl Interpreter {
                     if !filename.ends_with(".dbig") {
                         return;
                     }
-                    let content = fs::read_to_string(&filename).unwrap_or_default();
-                    let found =
-                        self.check_item_in_list(&content, &target_list_key, &target_item_or_key);
+                    let table = self.dbig_view(&filename);
+                    let found = table
+                        .get(&target_list_key)
+                        .is_some_and(|values| values.contains(&target_item_or_key));
                     self.handle_set_as_multiple(
                         i,
                         tokens,
@@ -264,8 +521,7 @@ impl Interpreter {
                 if !filename.ends_with(".dbig") {
                     return;
                 }
-                let content = fs::read_to_string(&filename).unwrap_or_default();
-                let found = self.check_dbig_key(&content, &target_item_or_key);
+                let found = self.dbig_view(&filename).get(&target_item_or_key).is_some();
                 self.handle_set_as_multiple(
                     i,
                     tokens,
@@ -280,198 +536,699 @@ impl Interpreter {
     }
 
     fn handle_dbig_scan(&mut self, i: &mut usize, tokens: &Vec<Token>) {
-        // Syntax: dbig check keys value < 10 @"file.dbig"
-        *i += 1; // Skip "keys"
-
-        if *i < tokens.len() && tokens[*i].token_type == TokenType::Value {
-            *i += 1; // Skip "value"
-
-            // Get Operator
-            let operator = tokens[*i].token_type.clone();
-            *i += 1;
+        // Syntax: dbig check keys|map [where] COND [and|or COND]... [sort by key|value [desc]] [limit N [skip M]] @"file.dbig"
+        // COND:   key starts with "x" | key matches "regex" | key = "x"
+        //         value > 10 (also < = >= <= !=) | value contains "x"
+        // 'keys' returns the matching keys, 'map' returns a list of {"key": .., "values": [..]} maps.
+        let as_map = tokens[*i].token_type == TokenType::Map;
+        *i += 1; // Skip "keys" / "map"
+
+        let Some(query) = self.read_dbig_query(i, tokens) else {
+            return;
+        };
 
-            // Get Target Value
-            let target_val_str = self.get_token_value(&tokens[*i]);
-            let target_num = target_val_str.parse::<f64>().unwrap_or(0.0);
+        if *i < tokens.len() && tokens[*i].token_type == TokenType::At {
+            if !self.validate_at_strictness(*i, tokens) {
+                return;
+            }
             *i += 1;
 
-            if *i < tokens.len() && tokens[*i].token_type == TokenType::At {
-                if !self.validate_at_strictness(*i, tokens) {
+            if *i < tokens.len() {
+                let filename_raw = self.get_token_value(&tokens[*i]);
+                let filename = self.interpolate_string(&filename_raw);
+                if !filename.ends_with(".dbig") {
                     return;
                 }
-                *i += 1;
+                let table = self.dbig_view(&filename);
+                let matches = query.run(&table);
+
+                if as_map {
+                    let rows: Vec<Value> = matches
+                        .iter()
+                        .map(|(key, values)| serde_json::json!({ "key": key, "values": values }))
+                        .collect();
+                    self.set_json_result(i, tokens, Value::Array(rows).to_string());
+                } else {
+                    let keys = matches.into_iter().map(|(key, _)| key.clone()).collect();
+                    self.handle_set_as_multiple(i, tokens, keys);
+                }
+            }
+        }
+    }
 
-                if *i < tokens.len() {
+    // Reads the query up to the '@'. Leaves 'i' on the '@'.
+    fn read_dbig_query(&mut self, i: &mut usize, tokens: &Vec<Token>) -> Option<DbigQuery> {
+        let mut query = DbigQuery {
+            groups: vec![Vec::new()],
+            sort: None,
+            limit: None,
+            skip: 0,
+        };
+
+        while *i < tokens.len() && tokens[*i].token_type != TokenType::At {
+            let token = tokens[*i].token_type.clone();
+            *i += 1;
+            match token {
+                TokenType::Identifier(ref w) if w.eq_ignore_ascii_case("where") || w.eq_ignore_ascii_case("and") => {}
+                TokenType::Or => query.groups.push(Vec::new()),
+                TokenType::Key => {
+                    let word = self.next_dbig_word(i, tokens);
+                    if word == "starts" && *i < tokens.len() && tokens[*i].token_type == TokenType::With {
+                        *i += 1;
+                    }
+                    let target = self.next_dbig_target(i, tokens)?;
+                    let cond = match word.as_str() {
+                        "starts" => DbigCond::KeyStarts(target),
+                        "contains" => DbigCond::KeyContains(target),
+                        "=" => DbigCond::KeyIs(target),
+                        "matches" => match Regex::new(&target) {
+                            Ok(re) => DbigCond::KeyMatches(re),
+                            Err(e) => {
+                                println!("Big Error: DBB query has a bad regex '{}'. {}", target, e);
+                                return None;
+                            }
+                        },
+                        _ => {
+                            println!("Big Error: DBB query expects 'key starts with', 'key matches', 'key contains' or 'key ='. (Line {})", tokens[*i - 1].line);
+                            return None;
+                        }
+                    };
+                    query.groups.last_mut()?.push(cond);
+                }
+                TokenType::Value => {
+                    if *i >= tokens.len() {
+                        return None;
+                    }
+                    let op = tokens[*i].token_type.clone();
+                    let cond = match op {
+                        TokenType::Greater
+                        | TokenType::Less
+                        | TokenType::Assign
+                        | TokenType::GreaterEqual
+                        | TokenType::LessEqual
+                        | TokenType::NotEqual => {
+                            *i += 1;
+                            DbigCond::Compare(op, self.next_dbig_target(i, tokens)?)
+                        }
+                        TokenType::Identifier(ref w) if w.eq_ignore_ascii_case("contains") => {
+                            *i += 1;
+                            DbigCond::ValueContains(self.next_dbig_target(i, tokens)?)
+                        }
+                        _ => {
+                            println!("Big Error: DBB query expects a comparison or 'contains' after 'value'. (Line {})", tokens[*i].line);
+                            return None;
+                        }
+                    };
+                    query.groups.last_mut()?.push(cond);
+                }
+                TokenType::Sort => {
+                    if *i < tokens.len() && tokens[*i].token_type == TokenType::By {
+                        *i += 1;
+                    }
+                    let by_value = *i < tokens.len() && tokens[*i].token_type == TokenType::Value;
+                    *i += 1; // Skip "key" / "value"
+                    let desc = matches!(tokens.get(*i).map(|t| &t.token_type), Some(TokenType::Identifier(w)) if w.eq_ignore_ascii_case("desc"));
+                    if desc {
+                        *i += 1;
+                    }
+                    query.sort = Some((by_value, desc));
+                }
+                TokenType::Limit => {
+                    query.limit = self.next_dbig_target(i, tokens)?.parse::<usize>().ok();
+                }
+                TokenType::Identifier(ref w) if w.eq_ignore_ascii_case("skip") => {
+                    query.skip = self.next_dbig_target(i, tokens)?.parse::<usize>().unwrap_or(0);
+                }
+                _ => {
+                    println!("Big Error: DBB query does not understand {:?}. (Line {})", token, tokens[*i - 1].line);
+                    return None;
+                }
+            }
+        }
+        Some(query)
+    }
+
+    // The word after 'key': starts / matches / contains, or "=" for an exact match.
+    fn next_dbig_word(&self, i: &mut usize, tokens: &Vec<Token>) -> String {
+        let word = match tokens.get(*i).map(|t| &t.token_type) {
+            Some(TokenType::Assign) => "=".to_string(),
+            Some(TokenType::Identifier(w)) => w.to_lowercase(),
+            _ => String::new(),
+        };
+        *i += 1;
+        word
+    }
+
+    fn next_dbig_target(&mut self, i: &mut usize, tokens: &Vec<Token>) -> Option<String> {
+        if *i >= tokens.len() || tokens[*i].token_type == TokenType::At {
+            println!("Big Error: DBB query is missing a value before '@'.");
+            return None;
+        }
+        let raw = self.get_token_value(&tokens[*i]);
+        *i += 1;
+        Some(self.interpolate_string(&raw))
+    }
+
+    fn handle_dbig_batch(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        // Syntax: dbig begin @"file.dbig"  ...  dbig commit  (or dbig rollback)
+        let action = self.get_token_raw_name(&tokens[*i]).to_lowercase();
+
+        match action.as_str() {
+            "begin" => {
+                if *i + 1 < tokens.len() && tokens[*i + 1].token_type == TokenType::At {
+                    *i += 1;
+                    if !self.validate_at_strictness(*i, tokens) {
+                        return;
+                    }
+                    *i += 1;
+                    if *i >= tokens.len() {
+                        return;
+                    }
                     let filename_raw = self.get_token_value(&tokens[*i]);
                     let filename = self.interpolate_string(&filename_raw);
+
                     if !filename.ends_with(".dbig") {
+                        println!("Big Error: DBB only works with .dbig files!");
                         return;
                     }
-                    let content = fs::read_to_string(&filename).unwrap_or_default();
-
-                    let matches =
-                        self.scan_dbig_values(&content, &operator, target_num, &target_val_str);
-                    self.handle_set_as_multiple(i, tokens, matches);
+                    if let Some((open, _)) = &self.dbig_batch {
+                        println!("Big Error: DBB already has an open batch on '{}'. Commit or roll it back first.", open);
+                        return;
+                    }
+                    self.dbig_batch = Some((filename, Vec::new()));
+                } else {
+                    println!("Big Error: Expected @\"file.dbig\" after 'dbig begin'.");
+                }
+            }
+            "commit" => match self.dbig_batch.take() {
+                Some((filename, ops)) => {
+                    let saved = self.dbig_write(&filename, |table| {
+                        for op in &ops {
+                            op.apply(table);
+                        }
+                    });
+                    // The queued writes are gone either way, so a failed commit is a catchable bug
+                    if !saved {
+                        self.last_bug_found = true;
+                        self.last_bug_type = format!("DBB Error: The batch on '{}' was not committed.", filename);
+                        self.set_variable("BugType".to_string(), self.last_bug_type.clone());
+                    }
                 }
+                None => println!("Big Error: 'dbig commit' without 'dbig begin'."),
+            },
+            _ => {
+                self.dbig_batch = None;
             }
         }
     }
 
-    // --- PARSING LOGIC ---
+    fn handle_dbig_sweep(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        // Syntax: dbig sweep @"file.dbig" [& set as {Removed}]
+        *i += 1; // Skip "sweep"
 
-    fn parse_dbig_content(&self, content: &str, target_key: &str) -> Vec<String> {
-        let mut results = Vec::new();
-        let mut inside_target = false;
-
-        for line in content.lines() {
-            let trim = line.trim();
-            if trim.is_empty() {
-                continue;
+        if *i < tokens.len() && tokens[*i].token_type == TokenType::At {
+            if !self.validate_at_strictness(*i, tokens) {
+                return;
             }
+            *i += 1;
 
-            if trim.starts_with('[') && trim.ends_with(']') {
-                let current_key = &trim[1..trim.len() - 1];
-                inside_target = current_key == target_key;
-            } else if inside_target
-                && trim.starts_with("- ") && trim.ends_with(" |") {
-                    let val = &trim[2..trim.len() - 2];
-                    results.push(val.to_string());
+            if *i < tokens.len() {
+                let filename_raw = self.get_token_value(&tokens[*i]);
+                let filename = self.interpolate_string(&filename_raw);
+
+                if !filename.ends_with(".dbig") {
+                    println!("Big Error: DBB only works with .dbig files!");
+                    return;
+                }
+
+                let mut removed = 0;
+                if self.dbig_read(&filename).has_expired(unix_now()) {
+                    self.dbig_write(&filename, |table| removed = table.sweep(unix_now()));
                 }
+                self.handle_set_as_multiple(i, tokens, vec![removed.to_string()]);
+            }
         }
-        results
     }
 
-    fn update_dbig_content(
-        &self,
-        content: &str,
-        target_key: &str,
-        new_values: Vec<String>,
-    ) -> String {
-        let mut new_lines = Vec::new();
-        let mut skipping = false;
+    // --- COMMAND LINE ---
 
-        for line in content.lines() {
-            let trim = line.trim();
+    // 'bigrun dbig <file> <command> [args]'
+    pub fn run_dbig_cli(&mut self, filename: &str, args: &[String]) -> Result<(), String> {
+        if !filename.ends_with(".dbig") {
+            return Err(String::from("DBB only works with .dbig files!"));
+        }
+        let command = args.first().map(|c| c.as_str()).unwrap_or("keys");
+        let creates = matches!(command, "set" | "import");
+        if !creates && !std::path::Path::new(filename).exists() {
+            return Err(format!("Could not find '{}'.", filename));
+        }
 
-            if trim.starts_with('[') && trim.ends_with(']') {
-                let current_key = &trim[1..trim.len() - 1];
-                skipping = current_key == target_key;
+        match command {
+            "keys" => {
+                for (key, _) in self.dbig_view(filename).entries() {
+                    println!("{}", key);
+                }
             }
-
-            if !skipping {
-                new_lines.push(line.to_string());
+            "get" => {
+                let key = args.get(1).ok_or("Usage: bigrun dbig <file> get <key>")?;
+                let table = self.dbig_view(filename);
+                let values = table.get(key).ok_or(format!("No key '{}'.", key))?;
+                for val in values {
+                    println!("{}", val);
+                }
             }
+            "set" => {
+                let key = args.get(1).ok_or("Usage: bigrun dbig <file> set <key> <value>...")?;
+                let values = args[2..].to_vec();
+                if !self.dbig_write(filename, |table| table.set(key, values)) {
+                    return Err(format!("Could not save '{}'.", filename));
+                }
+            }
+            "remove" => {
+                let key = args.get(1).ok_or("Usage: bigrun dbig <file> remove <key>")?;
+                let mut found = false;
+                if !self.dbig_write(filename, |table| found = table.remove(key)) {
+                    return Err(format!("Could not save '{}'.", filename));
+                }
+                if !found {
+                    return Err(format!("No key '{}'.", key));
+                }
+            }
+            "dump" => {
+                let mut map = serde_json::Map::new();
+                for (key, values) in self.dbig_view(filename).entries() {
+                    map.insert(key.clone(), Value::from(values.clone()));
+                }
+                println!("{}", serde_json::to_string_pretty(&Value::Object(map)).unwrap_or_default());
+            }
+            "import" => {
+                let json_path = args.get(1).ok_or("Usage: bigrun dbig <file> import <data.json>")?;
+                let text = fs::read_to_string(json_path).map_err(|e| format!("Could not read '{}'. {}", json_path, e))?;
+                let parsed: Value = serde_json::from_str(&text).map_err(|e| format!("'{}' is not valid JSON. {}", json_path, e))?;
+                let Value::Object(map) = parsed else {
+                    return Err(String::from("Import expects a JSON object of key -> value or list of values."));
+                };
+                let count = map.len();
+                if !self.dbig_write(filename, |table| {
+                    for (key, val) in &map {
+                        let values = match val {
+                            Value::Array(items) => items.iter().map(json_to_dbig).collect(),
+                            other => vec![json_to_dbig(other)],
+                        };
+                        table.set(key, values);
+                    }
+                }) {
+                    return Err(format!("Could not save '{}'.", filename));
+                }
+                println!("DBB: Imported {} key(s) into '{}'.", count, filename);
+            }
+            "validate" => {
+                let content = fs::read_to_string(filename).map_err(|e| e.to_string())?;
+                let problems = validate_dbig(&content);
+                if problems.is_empty() {
+                    println!("DBB: '{}' is valid ({} keys).", filename, DbigTable::parse(&content).entries().len());
+                } else {
+                    for (line, msg) in &problems {
+                        println!("Line {}: {}", line, msg);
+                    }
+                    return Err(format!("{} problem(s) in '{}'.", problems.len(), filename));
+                }
+            }
+            other => return Err(format!("Unknown dbig command '{}'. Use keys, get, set, remove, dump, import or validate.", other)),
         }
+        Ok(())
+    }
+
+    // --- STORAGE ---
 
-        new_lines.push(String::new());
-        new_lines.push(format!("[{}]", target_key));
-        for val in new_values {
-            new_lines.push(format!("- {} |", val));
+    // Writes now, or queues the write when a batch is open on this file.
+    fn dbig_apply(&mut self, filename: &str, op: DbigOp) {
+        if let Some((open, ops)) = &mut self.dbig_batch {
+            if open == filename {
+                ops.push(op);
+                return;
+            }
         }
-        new_lines.push(String::new());
+        self.dbig_write(filename, |table| op.apply(table));
+    }
 
-        new_lines.join("\n")
+    // What a script sees: the file plus any writes still pending in its batch,
+    // minus expired keys.
+    fn dbig_view(&self, filename: &str) -> Arc<DbigTable> {
+        let mut table = self.dbig_read(filename);
+        if let Some((open, ops)) = &self.dbig_batch {
+            if open == filename && !ops.is_empty() {
+                let mut pending = (*table).clone();
+                for op in ops {
+                    op.apply(&mut pending);
+                }
+                table = Arc::new(pending);
+            }
+        }
+        let now = unix_now();
+        if table.has_expired(now) {
+            let mut live = (*table).clone();
+            live.sweep(now);
+            table = Arc::new(live);
+        }
+        table
     }
 
-    fn remove_dbig_block(&self, content: &str, target_key: &str) -> String {
-        let mut new_lines = Vec::new();
-        let mut skipping = false;
+    // Called from 'report_error': a halted script never commits half a batch.
+    pub fn discard_dbig_batch(&mut self) {
+        if let Some((filename, ops)) = self.dbig_batch.take() {
+            println!("DBB: Discarded {} pending write(s) to '{}'.", ops.len(), filename);
+        }
+    }
 
-        for line in content.lines() {
-            let trim = line.trim();
 
-            if trim.starts_with('[') && trim.ends_with(']') {
-                let current_key = &trim[1..trim.len() - 1];
-                skipping = current_key == target_key;
+    // The table for 'filename', parsed once and reused until the file changes on disk.
+    // A missing file is an empty table.
+    pub fn dbig_read(&self, filename: &str) -> Arc<DbigTable> {
+        let checked_at = SystemTime::now();
+        let stamp = file_stamp(filename);
+        let cached = match self.dbig_tables.lock() {
+            Ok(cache) => cache.get(filename).filter(|c| c.stamp == stamp).cloned(),
+            Err(_) => None,
+        };
+        if let Some(ref cached) = cached {
+            if stamp_settled(stamp, cached.checked_at) {
+                return cached.table.clone();
             }
+        }
 
-            if !skipping {
-                new_lines.push(line.to_string());
-            }
+        let content = fs::read_to_string(filename).unwrap_or_default();
+        let hash = blake3::hash(content.as_bytes());
+        let table = match cached {
+            Some(cached) if cached.hash == hash => cached.table,
+            _ => Arc::new(DbigTable::parse(&content)),
+        };
+        if let Ok(mut cache) = self.dbig_tables.lock() {
+            cache.insert(
+                filename.to_string(),
+                DbigCached {
+                    stamp,
+                    checked_at,
+                    hash,
+                    table: table.clone(),
+                },
+            );
         }
-        new_lines.join("\n")
+        table
     }
 
-    fn check_dbig_key(&self, content: &str, target_key: &str) -> bool {
-        for line in content.lines() {
-            let trim = line.trim();
-            if trim.starts_with('[') && trim.ends_with(']') {
-                let current_key = &trim[1..trim.len() - 1];
-                if current_key == target_key {
-                    return true;
-                }
+    // Applies 'change' under the writer lock and replaces the file atomically:
+    // the new text goes to its own 'file.dbig.<id>.tmp', is synced, then renamed over the old file.
+    // The tmp name is unique, so a writer that took over a stale lock can't write into another's.
+    // A crash at any point leaves either the old or the new file, never half of one.
+    pub fn dbig_write(&self, filename: &str, change: impl FnOnce(&mut DbigTable)) -> bool {
+        let _lock = match DbigLock::acquire(filename) {
+            Ok(lock) => lock,
+            Err(e) => {
+                println!("Big Error: DBB could not save '{}'. {}", filename, e);
+                return false;
             }
+        };
+        let mut table = (*self.dbig_read(filename)).clone(); // Fresh: another process may have written
+        change(&mut table);
+
+        let text = table.to_text();
+        let checked_at = SystemTime::now();
+        let tmp_path = format!("{}.{}.tmp", filename, uuid::Uuid::new_v4().simple());
+        let saved = fs::File::create(&tmp_path)
+            .and_then(|mut f| {
+                f.write_all(text.as_bytes())?;
+                f.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp_path, filename));
+        if let Err(e) = saved {
+            let _ = fs::remove_file(&tmp_path);
+            println!("Big Error: DBB could not save '{}'. {}", filename, e);
+            return false;
+        }
+
+        if let Ok(mut cache) = self.dbig_tables.lock() {
+            cache.insert(
+                filename.to_string(),
+                DbigCached {
+                    stamp: file_stamp(filename),
+                    checked_at,
+                    hash: blake3::hash(text.as_bytes()),
+                    table: Arc::new(table),
+                },
+            );
         }
-        false
+        true
     }
+}
 
-    fn check_item_in_list(&self, content: &str, target_key: &str, target_item: &str) -> bool {
-        let mut inside_target = false;
-        for line in content.lines() {
-            let trim = line.trim();
-            if trim.is_empty() {
-                continue;
+// Lines the parser would skip or misread, as (line number, message).
+pub fn validate_dbig(content: &str) -> Vec<(usize, String)> {
+    let mut problems = Vec::new();
+    let mut seen = HashMap::new();
+    let mut in_block = false;
+    for (idx, line) in content.lines().enumerate() {
+        let num = idx + 1;
+        let trim = line.trim();
+        if trim.is_empty() {
+            continue;
+        }
+        if let Some(key) = block_key(trim) {
+            if key.is_empty() {
+                problems.push((num, String::from("Empty key '[]'.")));
+            } else if let Some(first) = seen.insert(key.to_string(), num) {
+                problems.push((num, format!("Key '{}' was already opened on line {}; the values are merged.", key, first)));
             }
-
-            if trim.starts_with('[') && trim.ends_with(']') {
-                let current_key = &trim[1..trim.len() - 1];
-                inside_target = current_key == target_key;
-            } else if inside_target
-                && trim.starts_with("- ") && trim.ends_with(" |") {
-                    let val = &trim[2..trim.len() - 2];
-                    if val == target_item {
-                        return true;
-                    }
-                }
+            in_block = true;
+        } else if block_value(trim).is_some() || block_expiry(trim).is_some() {
+            if !in_block {
+                problems.push((num, String::from("Value before any [Key] header.")));
+            }
+        } else if trim.starts_with("@expires") {
+            problems.push((num, format!("Bad expiry '{}'. Expected '@expires <unix seconds>'.", trim)));
+        } else {
+            problems.push((num, format!("Not a '- value |' line: '{}'", trim)));
         }
-        false
     }
+    problems
+}
 
-    fn scan_dbig_values(
-        &self,
-        content: &str,
-        op: &TokenType,
-        target_num: f64,
-        target_str: &str,
-    ) -> Vec<String> {
-        let mut matches = Vec::new();
-        let mut current_key = String::new();
+// JSON scalars become their text; nested values are stored as JSON text.
+fn json_to_dbig(val: &Value) -> String {
+    match val {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
 
-        for line in content.lines() {
-            let trim = line.trim();
-            if trim.is_empty() {
-                continue;
-            }
+// --- QUERY ---
+pub enum DbigCond {
+    KeyStarts(String),
+    KeyContains(String),
+    KeyIs(String),
+    KeyMatches(Regex),
+    Compare(TokenType, String),
+    ValueContains(String),
+}
 
-            if trim.starts_with('[') && trim.ends_with(']') {
-                current_key = trim[1..trim.len() - 1].to_string();
-            } else if !current_key.is_empty()
-                && trim.starts_with("- ") && trim.ends_with(" |") {
-                    let val = &trim[2..trim.len() - 2];
+// Conditions are OR-ed groups of AND-ed conditions, so 'and' binds tighter than 'or'.
+pub struct DbigQuery {
+    groups: Vec<Vec<DbigCond>>,
+    sort: Option<(bool, bool)>, // (by value, descending)
+    limit: Option<usize>,
+    skip: usize,
+}
 
-                    let is_match = if let Ok(val_num) = val.parse::<f64>() {
-                        match op {
-                            TokenType::Greater => val_num > target_num,
-                            TokenType::Less => val_num < target_num,
-                            TokenType::Assign => (val_num - target_num).abs() < 0.0001,
-                            _ => false,
-                        }
-                    } else {
-                        // String comparison
-                        match op {
-                            TokenType::Assign => val == target_str,
-                            _ => false,
-                        }
-                    };
+impl DbigCond {
+    fn test(&self, key: &str, values: &[String]) -> bool {
+        match self {
+            DbigCond::KeyStarts(p) => key.starts_with(p.as_str()),
+            DbigCond::KeyContains(p) => key.contains(p.as_str()),
+            DbigCond::KeyIs(p) => key == p,
+            DbigCond::KeyMatches(re) => re.is_match(key),
+            // Value conditions hold when any one of the key's values passes
+            DbigCond::ValueContains(p) => values.iter().any(|v| v.contains(p.as_str())),
+            DbigCond::Compare(op, target) => values.iter().any(|v| compare_dbig_value(v, op, target)),
+        }
+    }
+}
 
-                    if is_match {
-                        matches.push(current_key.clone());
-                    }
+impl DbigQuery {
+    pub fn run<'a>(&self, table: &'a DbigTable) -> Vec<&'a (String, Vec<String>)> {
+        let mut matches: Vec<&(String, Vec<String>)> = table
+            .entries()
+            .iter()
+            .filter(|(key, values)| {
+                self.groups.iter().any(|group| group.iter().all(|c| c.test(key, values)))
+            })
+            .collect();
+
+        if let Some((by_value, desc)) = self.sort {
+            matches.sort_by(|a, b| {
+                let order = if by_value {
+                    let first = |e: &(String, Vec<String>)| e.1.first().cloned().unwrap_or_default();
+                    order_dbig_values(&first(a), &first(b))
+                } else {
+                    a.0.cmp(&b.0)
+                };
+                if desc {
+                    order.reverse()
+                } else {
+                    order
                 }
+            });
         }
-        // Remove duplicates if a key has multiple matching values?
-        // Yes, scanning generally returns Keys.
-        matches.dedup();
+
         matches
+            .into_iter()
+            .skip(self.skip)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+// Numbers compare as numbers and text as text. A number never orders against text,
+// so 'value > 10' skips values like "abc" (only '!=' holds between the two).
+fn compare_dbig_value(val: &str, op: &TokenType, target: &str) -> bool {
+    let order = match (val.parse::<f64>(), target.parse::<f64>()) {
+        (Ok(a), Ok(b)) => {
+            if (a - b).abs() < 0.0001 {
+                std::cmp::Ordering::Equal
+            } else {
+                a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+            }
+        }
+        (Err(_), Err(_)) => val.cmp(target),
+        _ => return *op == TokenType::NotEqual,
+    };
+    match op {
+        TokenType::Greater => order.is_gt(),
+        TokenType::Less => order.is_lt(),
+        TokenType::Assign => order.is_eq(),
+        TokenType::GreaterEqual => order.is_ge(),
+        TokenType::LessEqual => order.is_le(),
+        TokenType::NotEqual => order.is_ne(),
+        _ => false,
+    }
+}
+
+// Sort order for 'sort by value': numbers first (numerically), then text.
+fn order_dbig_values(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or(std::cmp::Ordering::Equal),
+        (Ok(_), Err(_)) => std::cmp::Ordering::Less,
+        (Err(_), Ok(_)) => std::cmp::Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(list: &[&str]) -> Vec<String> {
+        list.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn legacy_files_round_trip_without_losing_lines() {
+        let legacy = "# Users, kept by hand\n\
+                      [Ann]\n\
+                      \x20 - 3 |\n\
+                      - x |\n\
+                      \n\
+                      // bob below\n\
+                      [Bob]\n\
+                      @expires 4000000000\n\
+                      - 4 |\n\
+                      [Ann]\n\
+                      - more |\n\
+                      trailing note\n";
+        let table = DbigTable::parse(legacy);
+        assert_eq!(table.get("Ann"), Some(&values(&["3", "x", "more"])));
+        assert_eq!(table.get("Bob"), Some(&values(&["4"])));
+        assert_eq!(table.expires_at("Bob"), Some(4_000_000_000));
+
+        let text = table.to_text();
+        for line in ["# Users, kept by hand", "// bob below", "trailing note"] {
+            assert!(text.contains(line), "lost '{}'", line);
+        }
+        assert!(text.find("// bob below") < text.find("[Bob]"));
+        // Canonical text reads back to itself
+        assert_eq!(DbigTable::parse(&text).to_text(), text);
+
+        // A removed block hands its notes to the next one
+        let mut table = DbigTable::parse(&text);
+        assert!(table.remove("Bob"));
+        assert!(!table.remove("Bob"));
+        let text = table.to_text();
+        assert!(text.contains("// bob below") && !text.contains("[Bob]"));
+    }
+
+    #[test]
+    fn query_groups_sort_and_page() {
+        let mut table = DbigTable::default();
+        table.set("user:ann", values(&["30"]));
+        table.set("user:bob", values(&["7"]));
+        table.set("user:cy", values(&["abc"]));
+        table.set("team:ops", values(&["12"]));
+
+        // (key starts "user:" and value > 10) or key is "team:ops"
+        let query = DbigQuery {
+            groups: vec![
+                vec![
+                    DbigCond::KeyStarts(String::from("user:")),
+                    DbigCond::Compare(TokenType::Greater, String::from("10")),
+                ],
+                vec![DbigCond::KeyIs(String::from("team:ops"))],
+            ],
+            sort: Some((true, true)),
+            limit: None,
+            skip: 0,
+        };
+        let keys: Vec<&str> = query.run(&table).iter().map(|e| e.0.as_str()).collect();
+        assert_eq!(keys, vec!["user:ann", "team:ops"]);
+
+        // Text never orders against numbers; only '!=' holds
+        let query = DbigQuery {
+            groups: vec![vec![DbigCond::Compare(TokenType::NotEqual, String::from("7"))]],
+            sort: Some((false, false)),
+            limit: Some(2),
+            skip: 1,
+        };
+        let keys: Vec<&str> = query.run(&table).iter().map(|e| e.0.as_str()).collect();
+        assert_eq!(keys, vec!["user:ann", "user:cy"]);
+
+        let query = DbigQuery {
+            groups: vec![vec![DbigCond::KeyMatches(Regex::new("^user:[a-b]").unwrap())]],
+            sort: Some((true, false)),
+            limit: None,
+            skip: 0,
+        };
+        let keys: Vec<&str> = query.run(&table).iter().map(|e| e.0.as_str()).collect();
+        assert_eq!(keys, vec!["user:bob", "user:ann"]);
+    }
+
+    #[test]
+    fn sweep_drops_only_expired_keys() {
+        let mut table = DbigTable::default();
+        table.set("a", values(&["1"]));
+        table.set("b", values(&["2"]));
+        table.set("c", values(&["3"]));
+        table.expire("a", 100);
+        table.expire("b", 200);
+        table.expire("missing", 50);
+
+        assert!(!table.has_expired(99));
+        assert!(table.has_expired(100));
+        assert_eq!(table.sweep(150), 1);
+        assert_eq!(table.get("a"), None);
+        assert_eq!(table.expires_at("b"), Some(200));
+
+        // Setting a key again clears its expiry
+        table.set("b", values(&["22"]));
+        assert_eq!(table.sweep(1000), 0);
+        let keys: Vec<&str> = table.entries().iter().map(|e| e.0.as_str()).collect();
+        assert_eq!(keys, vec!["b", "c"]);
     }
 }
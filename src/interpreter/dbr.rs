@@ -1,17 +1,55 @@
 use super::Interpreter;
+use crate::lexer::Lexer;
 use crate::tokens::{Token, TokenType};
-use rusqlite::Connection;
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params_from_iter, Connection, OpenFlags, Row};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// A cached connection. The map lock is only held to find it; statements run under the
+// connection's own lock, so workers using different databases never wait on each other.
+pub struct SqlConnection {
+    pub conn: Arc<Mutex<Connection>>,
+    pub named: bool, // From 'connect sql': kept until 'close sql'
+    pub last_used: Instant,
+}
+
+impl SqlConnection {
+    fn new(conn: Connection, named: bool) -> Self {
+        SqlConnection {
+            conn: Arc::new(Mutex::new(conn)),
+            named,
+            last_used: Instant::now(),
+        }
+    }
+}
+
+// Path connections opened on first use are closed after this long unused,
+// and the least recently used go first once there are more than SQL_MAX_PATHS.
+const SQL_IDLE: Duration = Duration::from_secs(300);
+const SQL_MAX_PATHS: usize = 32;
 
 impl Interpreter {
     pub fn handle_use_sql(&mut self, i: &mut usize, tokens: &Vec<Token>) {
         *i += 1;
         if *i < tokens.len() && tokens[*i].token_type == TokenType::Sql {
             self.sql_enabled = true;
+            // use sql strict
+            if *i + 1 < tokens.len()
+                && tokens[*i + 1].token_type == TokenType::Identifier(String::from("strict"))
+            {
+                *i += 1;
+                self.sql_strict = true;
+            }
         }
     }
 
     pub fn handle_run_sql(&mut self, i: &mut usize, tokens: &Vec<Token>) {
-        // Syntax: run sql "query" on "db.file"
+        // Syntax: run sql "query" [with {A}, {B}] on "db.file" (or a connection name)
         *i += 2; // Skip "run" and "sql"
 
         if !self.sql_enabled {
@@ -20,8 +58,9 @@ impl Interpreter {
         }
 
         if *i < tokens.len() {
-            let query_raw = self.get_token_value(&tokens[*i]);
-            let query = self.interpolate_string(&query_raw);
+            let Some((query, params)) = self.read_sql_query(i, tokens) else {
+                return;
+            };
 
             *i += 1;
             if *i < tokens.len() && tokens[*i].token_type == TokenType::On {
@@ -29,19 +68,18 @@ impl Interpreter {
                 let db_path_raw = self.get_token_value(&tokens[*i]);
                 let db_path = self.interpolate_string(&db_path_raw);
 
-                if let Ok(conn) = Connection::open(db_path) {
-                    if let Err(e) = conn.execute(&query, []) {
-                        println!("Big Error: SQL Run Failed - {}", e);
-                    }
-                } else {
-                    println!("Big Error: Failed to open database.");
+                let result = self.with_sql_connection(&db_path, |conn| {
+                    conn.prepare_cached(&query)?.execute(params_from_iter(params))
+                });
+                if let Err(e) = result {
+                    self.sql_failed(&db_path, format!("SQL Run Failed - {}", e));
                 }
             }
         }
     }
 
     pub fn handle_get_sql(&mut self, i: &mut usize, tokens: &Vec<Token>) {
-        // Syntax: get sql "query" on "db.file" & set as list {Result}
+        // Syntax: get sql "query" [with {A}, {B}] on "db.file" & set as list {Result}
         *i += 2; // Skip "get" and "sql"
 
         if !self.sql_enabled {
@@ -50,8 +88,9 @@ impl Interpreter {
         }
 
         if *i < tokens.len() {
-            let query_raw = self.get_token_value(&tokens[*i]);
-            let query = self.interpolate_string(&query_raw);
+            let Some((query, params)) = self.read_sql_query(i, tokens) else {
+                return;
+            };
 
             *i += 1;
             if *i < tokens.len() && tokens[*i].token_type == TokenType::On {
@@ -59,25 +98,800 @@ impl Interpreter {
                 let db_path_raw = self.get_token_value(&tokens[*i]);
                 let db_path = self.interpolate_string(&db_path_raw);
 
-                if let Ok(conn) = Connection::open(db_path) {
-                    if let Ok(mut stmt) = conn.prepare(&query) {
-                        let rows = stmt.query_map([], |row| {
-                            let val: String = row.get(0).unwrap_or(String::from(""));
-                            Ok(val)
-                        });
-
-                        let mut results = Vec::new();
-                        if let Ok(iter) = rows {
-                            for s in iter.flatten() {
-                                results.push(s);
+                let result = self.with_sql_connection(&db_path, |conn| {
+                    let mut stmt = conn.prepare_cached(&query)?;
+                    let columns: Vec<String> =
+                        stmt.column_names().iter().map(|c| c.to_string()).collect();
+                    let rows = stmt.query_map(params_from_iter(params), |row| Ok(row_to_json(row, &columns)))?;
+                    rows.collect::<rusqlite::Result<Vec<_>>>()
+                });
+                let rows = match result {
+                    Ok(rows) => rows,
+                    Err(e) => {
+                        self.sql_failed(&db_path, format!("SQL Query Failed - {}", e));
+                        Vec::new()
+                    }
+                };
+                let result = serde_json::Value::Array(rows).to_string();
+                self.set_json_result(i, tokens, result);
+            }
+        }
+    }
+
+    pub fn handle_sql_session(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        // Syntax:
+        // connect sql "app.db" as "main" [wal] [wait 5000] [foreign keys] [cache 64]
+        // begin sql on "main"  /  commit sql [on "main"]  /  rollback sql [on "main"]
+        // close sql "main"
+        // connect/begin/commit/rollback are plain identifiers; 'close' is a keyword
+        let action = match &tokens[*i].token_type {
+            TokenType::Identifier(w) => w.to_lowercase(),
+            _ => String::from("close"),
+        };
+        *i += 2; // Skip action and "sql"
+
+        if !self.sql_enabled {
+            println!("Big Error: SQL engine is locked! You must write 'use sql' at the start of your file.");
+            return;
+        }
+        if *i > tokens.len() || tokens[*i - 1].token_type != TokenType::Sql {
+            println!("Big Error: Expected 'sql' after '{}'.", action);
+            return;
+        }
+
+        match action.as_str() {
+            "connect" => {
+                if *i >= tokens.len() {
+                    return;
+                }
+                let path_raw = self.get_token_value(&tokens[*i]);
+                let path = self.interpolate_string(&path_raw);
+                let mut name = path.clone();
+                if *i + 2 < tokens.len() && tokens[*i + 1].token_type == TokenType::As {
+                    *i += 2;
+                    name = self.get_token_value(&tokens[*i]);
+                }
+
+                let conn = match Connection::open(&path) {
+                    Ok(c) => c,
+                    Err(e) => {
+                        println!("Big Error: Failed to open database '{}'. {}", path, e);
+                        return;
+                    }
+                };
+
+                // Options (Rest of the line)
+                let line = tokens[*i].line;
+                while *i + 1 < tokens.len() && tokens[*i + 1].line == line {
+                    *i += 1;
+                    let applied = match tokens[*i].token_type {
+                        TokenType::Identifier(ref s) if s == "wal" => conn
+                            .pragma_update_and_check(None, "journal_mode", "WAL", |r| r.get::<_, String>(0))
+                            .map(|_| ()),
+                        TokenType::Identifier(ref s) if s == "foreign" => {
+                            if *i + 1 < tokens.len() && tokens[*i + 1].token_type == TokenType::Keys {
+                                *i += 1;
                             }
+                            conn.pragma_update(None, "foreign_keys", "ON")
+                        }
+                        TokenType::Wait if *i + 1 < tokens.len() => {
+                            *i += 1;
+                            let ms = self.get_token_value(&tokens[*i]).parse::<u64>().unwrap_or(5000);
+                            conn.busy_timeout(Duration::from_millis(ms))
+                        }
+                        TokenType::Identifier(ref s) if s == "cache" && *i + 1 < tokens.len() => {
+                            *i += 1;
+                            let size = self.get_token_value(&tokens[*i]).parse::<usize>().unwrap_or(16);
+                            conn.set_prepared_statement_cache_capacity(size);
+                            Ok(())
+                        }
+                        _ => {
+                            println!(
+                                "Big Error: Unknown connect option '{}'. Use wal, wait, foreign keys or cache.",
+                                self.get_token_value(&tokens[*i])
+                            );
+                            Ok(())
                         }
-                        self.handle_set_as_multiple(i, tokens, results);
+                    };
+                    if let Err(e) = applied {
+                        println!("Big Error: SQL Option Failed - {}", e);
+                    }
+                }
+
+                if let Ok(mut map) = self.sql_connections.lock() {
+                    map.insert(name, SqlConnection::new(conn, true));
+                }
+            }
+            "begin" => {
+                if *i + 1 >= tokens.len() || tokens[*i].token_type != TokenType::On {
+                    println!("Big Error: Use 'begin sql on \"db\"'.");
+                    return;
+                }
+                *i += 1;
+                let name_raw = self.get_token_value(&tokens[*i]);
+                let name = self.interpolate_string(&name_raw);
+                match self.with_sql_connection(&name, |conn| conn.execute_batch("BEGIN")) {
+                    Ok(_) => self.sql_transactions.push(name),
+                    Err(e) => self.sql_failed(&name, format!("SQL Begin Failed - {}", e)),
+                }
+            }
+            "commit" | "rollback" => {
+                let name = if *i + 1 < tokens.len() && tokens[*i].token_type == TokenType::On {
+                    *i += 1;
+                    let name_raw = self.get_token_value(&tokens[*i]);
+                    self.interpolate_string(&name_raw)
+                } else {
+                    *i -= 1; // Nothing after "sql"
+                    match self.sql_transactions.last() {
+                        Some(name) => name.clone(),
+                        None => {
+                            println!("Big Error: No open SQL transaction.");
+                            return;
+                        }
+                    }
+                };
+                let Some(pos) = self.sql_transactions.iter().rposition(|t| *t == name) else {
+                    println!("Big Error: No open SQL transaction on '{}'.", name);
+                    return;
+                };
+                self.sql_transactions.remove(pos);
+                let sql = if action == "commit" { "COMMIT" } else { "ROLLBACK" };
+                if let Err(e) = self.with_sql_connection(&name, |conn| conn.execute_batch(sql)) {
+                    self.sql_failed(&name, format!("SQL {} Failed - {}", sql, e));
+                }
+            }
+            "close" => {
+                if *i >= tokens.len() {
+                    return;
+                }
+                let name_raw = self.get_token_value(&tokens[*i]);
+                let name = self.interpolate_string(&name_raw);
+                if self.sql_transactions.contains(&name) {
+                    println!("Big Error: Close the transaction on '{}' first (commit or rollback).", name);
+                    return;
+                }
+                if let Ok(mut map) = self.sql_connections.lock() {
+                    map.remove(&name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn handle_migrate_sql(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        // Syntax: migrate sql "migrations" on "app.db" [status | dry] [& set as {Count}]
+        *i += 2; // Skip "migrate" and "sql"
+
+        if !self.sql_enabled {
+            println!("Big Error: SQL engine is locked! You must write 'use sql' at the start of your file.");
+            return;
+        }
+        if *i + 2 >= tokens.len() || tokens[*i + 1].token_type != TokenType::On {
+            println!("Big Error: Use 'migrate sql \"folder\" on \"db\"'.");
+            return;
+        }
+        let dir_raw = self.get_token_value(&tokens[*i]);
+        let dir = self.interpolate_string(&dir_raw);
+        *i += 2;
+        let db_raw = self.get_token_value(&tokens[*i]);
+        let db = self.interpolate_string(&db_raw);
+
+        let mut mode = "apply";
+        if *i + 1 < tokens.len() {
+            if let TokenType::Identifier(ref word) = tokens[*i + 1].token_type {
+                if word == "status" || word == "dry" {
+                    mode = if word == "status" { "status" } else { "dry" };
+                    *i += 1;
+                }
+            }
+        }
+
+        match self.run_migrations(&db, &dir, mode) {
+            Ok(count) => self.handle_set_as_multiple(i, tokens, vec![count.to_string()]),
+            Err(e) => {
+                // Skip '& set as' so 'if any bug found' sees the bug
+                while *i + 1 < tokens.len() && tokens[*i + 1].line == tokens[*i].line {
+                    *i += 1;
+                }
+                self.sql_bug(e);
+            }
+        }
+    }
+
+    // Applies (or lists, for "status" / "dry") the numbered .sql/.big files in 'dir' that
+    // 'bigc_migrations' doesn't know yet. Each one runs in its own transaction.
+    // Returns how many migrations were applied (or are pending, for status/dry).
+    pub fn run_migrations(&mut self, db: &str, dir: &str, mode: &str) -> Result<usize, String> {
+        if self.sql_transactions.iter().any(|t| t == db) {
+            return Err(format!("Migrations can't run inside the open transaction on '{}'.", db));
+        }
+
+        // (version, file name) sorted by version
+        let mut files: Vec<(i64, String)> = Vec::new();
+        let entries = fs::read_dir(dir).map_err(|e| format!("Can't read migrations folder '{}'. {}", dir, e))?;
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.ends_with(".sql") && !name.ends_with(".big") {
+                continue;
+            }
+            let digits: String = name.chars().take_while(|c| c.is_ascii_digit()).collect();
+            match digits.parse::<i64>() {
+                Ok(version) => files.push((version, name)),
+                Err(_) => println!("BigSQL: Skipping '{}' (no version number in front).", name),
+            }
+        }
+        files.sort();
+        if let Some(w) = files.windows(2).find(|w| w[0].0 == w[1].0) {
+            return Err(format!("Migrations '{}' and '{}' share version {}.", w[0].1, w[1].1, w[0].0));
+        }
+
+        let applied = if mode == "status" || mode == "dry" {
+            self.preview_applied_migrations(db)?
+        } else {
+            self.with_sql_connection(db, |conn| {
+                conn.execute_batch(
+                    "CREATE TABLE IF NOT EXISTS bigc_migrations (version INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at TEXT NOT NULL)",
+                )?;
+                applied_migrations(conn)
+            })?
+        };
+
+        println!("BigSQL: Migrations in '{}' for '{}'", dir, db);
+        let mut count = 0;
+        for (version, name) in files {
+            if let Some((applied_name, at)) = applied.get(&version) {
+                if mode == "status" {
+                    let note = if *applied_name != name {
+                        format!(" (applied as '{}')", applied_name)
                     } else {
-                        println!("Big Error: SQL Prepare Failed");
+                        String::new()
+                    };
+                    println!("  [applied {}] {}{}", at, name, note);
+                }
+                continue;
+            }
+            count += 1;
+            match mode {
+                "status" => println!("  [pending]{} {}", " ".repeat(20), name),
+                "dry" => println!("  would apply {}", name),
+                _ => {
+                    self.apply_migration(db, dir, version, &name)?;
+                    println!("  applied {}", name);
+                }
+            }
+        }
+        if count == 0 && mode != "status" {
+            println!("  nothing to do");
+        }
+        Ok(count)
+    }
+
+    // status/dry never write: a known connection is only queried, a path is opened read-only,
+    // and a missing database or 'bigc_migrations' table means nothing was applied yet.
+    fn preview_applied_migrations(&self, db: &str) -> Result<HashMap<i64, (String, String)>, String> {
+        let known = self
+            .sql_connections
+            .lock()
+            .map(|map| map.contains_key(db))
+            .unwrap_or(false);
+        if known {
+            return self.with_sql_connection(db, applied_migrations);
+        }
+        if !Path::new(db).exists() {
+            return Ok(HashMap::new());
+        }
+        let conn = Connection::open_with_flags(db, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("Failed to open database. {}", e))?;
+        applied_migrations(&conn).map_err(|e| e.to_string())
+    }
+
+    fn apply_migration(&mut self, db: &str, dir: &str, version: i64, name: &str) -> Result<(), String> {
+        let path = Path::new(dir).join(name);
+        let content = fs::read_to_string(&path).map_err(|e| format!("Can't read migration '{}'. {}", name, e))?;
+        let stamp = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let record = "INSERT INTO bigc_migrations (version, name, applied_at) VALUES (?1, ?2, ?3)";
+
+        if name.ends_with(".sql") {
+            return self
+                .with_sql_connection(db, |conn| {
+                    let tx = conn.unchecked_transaction()?;
+                    tx.execute_batch(&content)?;
+                    tx.execute(record, rusqlite::params![version, name, stamp])?;
+                    tx.commit()
+                })
+                .map_err(|e| format!("Migration '{}' failed, rolled back. {}", name, e));
+        }
+
+        // .big: runs like 'begin sql on db' ... 'commit sql'. A bug halts it and
+        // report_error rolls the transaction back. $MigrationDb names the database.
+        // The migration gets its own transaction list, so that rollback never touches the caller's.
+        self.with_sql_connection(db, |conn| conn.execute_batch("BEGIN"))?;
+        let outer = std::mem::take(&mut self.sql_transactions);
+        self.sql_transactions.push(db.to_string());
+        self.set_variable("MigrationDb".to_string(), db.to_string());
+
+        let mut lexer = Lexer::new(&content);
+        let migration_tokens = lexer.tokenize();
+        let depth = self.call_depth;
+        let scopes = self.local_scopes.len();
+        let source = std::mem::replace(&mut self.full_source, content.clone()); // Error boxes quote the migration
+        if self.validate_syntax(&migration_tokens) {
+            self.run(migration_tokens);
+        }
+        self.full_source = source;
+        self.return_triggered = false;
+        self.call_depth = depth;
+        self.local_scopes.truncate(scopes);
+
+        let mut opened = std::mem::replace(&mut self.sql_transactions, outer);
+        let own = opened.iter().rposition(|t| t == db).map(|pos| opened.remove(pos));
+        // Anything else the migration began and left open is its own too
+        for name in opened.into_iter().rev() {
+            if self.with_sql_connection(&name, |conn| conn.execute_batch("ROLLBACK")).is_ok() {
+                println!("BigSQL: Rolled back the open transaction on '{}'.", name);
+            }
+        }
+        match own {
+            Some(_) => {
+                self.with_sql_connection(db, |conn| {
+                    conn.execute(record, rusqlite::params![version, name, stamp])?;
+                    conn.execute_batch("COMMIT")
+                })
+                .map_err(|e| {
+                    let _ = self.with_sql_connection(db, |conn| conn.execute_batch("ROLLBACK"));
+                    format!("Migration '{}' failed, rolled back. {}", name, e)
+                })
+            }
+            None => Err(format!("Migration '{}' stopped on a bug and was rolled back.", name)),
+        }
+    }
+
+    pub fn handle_sql_transfer(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        // Syntax:
+        // export sql "select ..." [with {A}] on "db" to "rows.csv"  (or .json)
+        // import sql "people.csv" to "users" on "db" [map "Full Name" as "name"]...  (or .json)
+        // Both end with an optional '& set as {Count}'
+        let importing = tokens[*i].token_type != TokenType::Export; // 'import' is an identifier
+        *i += 2; // Skip import/export and "sql"
+
+        if !self.sql_enabled {
+            println!("Big Error: SQL engine is locked! You must write 'use sql' at the start of your file.");
+            return;
+        }
+        if *i >= tokens.len() {
+            return;
+        }
+
+        let result = if importing {
+            let file_raw = self.get_token_value(&tokens[*i]);
+            let file = self.interpolate_string(&file_raw);
+            if *i + 4 >= tokens.len()
+                || tokens[*i + 1].token_type != TokenType::To
+                || tokens[*i + 3].token_type != TokenType::On
+            {
+                println!("Big Error: Use 'import sql \"file\" to \"table\" on \"db\"'.");
+                return;
+            }
+            let table_raw = self.get_token_value(&tokens[*i + 2]);
+            let table = self.interpolate_string(&table_raw);
+            let db_raw = self.get_token_value(&tokens[*i + 4]);
+            let db = self.interpolate_string(&db_raw);
+            *i += 4;
+
+            // map "Header" as "column" (Repeatable, commas optional)
+            let mut mapping = HashMap::new();
+            loop {
+                let mut k = *i + 1;
+                if k < tokens.len() && tokens[k].token_type == TokenType::Char(',') {
+                    k += 1;
+                }
+                if k + 3 < tokens.len()
+                    && tokens[k].token_type == TokenType::Map
+                    && tokens[k + 2].token_type == TokenType::As
+                {
+                    let header_raw = self.get_token_value(&tokens[k + 1]);
+                    let column_raw = self.get_token_value(&tokens[k + 3]);
+                    let header = self.interpolate_string(&header_raw);
+                    let column = self.interpolate_string(&column_raw);
+                    mapping.insert(header, column);
+                    *i = k + 3;
+                } else {
+                    break;
+                }
+            }
+            self.import_table(&file, &table, &db, &mapping)
+        } else {
+            let Some((query, params)) = self.read_sql_query(i, tokens) else {
+                return;
+            };
+            if *i + 4 >= tokens.len()
+                || tokens[*i + 1].token_type != TokenType::On
+                || tokens[*i + 3].token_type != TokenType::To
+            {
+                println!("Big Error: Use 'export sql \"query\" on \"db\" to \"file\"'.");
+                return;
+            }
+            let db_raw = self.get_token_value(&tokens[*i + 2]);
+            let db = self.interpolate_string(&db_raw);
+            let file_raw = self.get_token_value(&tokens[*i + 4]);
+            let file = self.interpolate_string(&file_raw);
+            *i += 4;
+            self.export_query(&query, params, &db, &file)
+        };
+
+        match result {
+            Ok(count) => self.handle_set_as_multiple(i, tokens, vec![count.to_string()]),
+            Err(e) => {
+                // Skip '& set as' so 'if any bug found' sees the bug
+                while *i + 1 < tokens.len() && tokens[*i + 1].line == tokens[*i].line {
+                    *i += 1;
+                }
+                self.sql_bug(e);
+            }
+        }
+    }
+
+    // Writes the query result to a .csv (header row + values) or .json (list of row maps) file.
+    fn export_query(&mut self, query: &str, params: Vec<Value>, db: &str, file: &str) -> Result<usize, String> {
+        let format = file_format(file)?;
+        let (columns, rows) = self.with_sql_connection(db, |conn| {
+            let mut stmt = conn.prepare_cached(query)?;
+            let columns: Vec<String> = stmt.column_names().iter().map(|c| c.to_string()).collect();
+            let rows = stmt
+                .query_map(params_from_iter(params), |row| Ok(row_to_json(row, &columns)))?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok((columns, rows))
+        })?;
+
+        let written = if format == "json" {
+            let text = serde_json::to_string_pretty(&rows).unwrap_or_default();
+            fs::write(file, text).map_err(|e| e.to_string())
+        } else {
+            let mut writer = csv::Writer::from_path(file).map_err(|e| e.to_string())?;
+            let mut result = writer.write_record(&columns).map_err(|e| e.to_string());
+            for row in &rows {
+                let record: Vec<String> = columns
+                    .iter()
+                    .map(|c| match &row[c] {
+                        serde_json::Value::Null => String::new(),
+                        serde_json::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .collect();
+                result = result.and(writer.write_record(&record).map_err(|e| e.to_string()));
+            }
+            result.and(writer.flush().map_err(|e| e.to_string()))
+        };
+        written.map_err(|e| format!("Export to '{}' failed. {}", file, e))?;
+        Ok(rows.len())
+    }
+
+    // Bulk-loads a .csv (first row is the header) or .json (list of maps) file in one transaction.
+    // A missing table is created with column types inferred from the data.
+    fn import_table(&mut self, file: &str, table: &str, db: &str, mapping: &HashMap<String, String>) -> Result<usize, String> {
+        let format = file_format(file)?;
+        let text = fs::read_to_string(file).map_err(|e| format!("Can't read '{}'. {}", file, e))?;
+
+        // Source columns (headers / keys in first-seen order) and rows of typed values
+        let mut headers: Vec<String> = Vec::new();
+        let mut rows: Vec<Vec<Value>> = Vec::new();
+        if format == "json" {
+            let parsed: serde_json::Value =
+                serde_json::from_str(&text).map_err(|e| format!("'{}' is not valid JSON. {}", file, e))?;
+            let items = parsed
+                .as_array()
+                .ok_or_else(|| format!("'{}' must hold a list of maps.", file))?;
+            for item in items {
+                if let Some(map) = item.as_object() {
+                    for key in map.keys() {
+                        if !headers.contains(key) {
+                            headers.push(key.clone());
+                        }
+                    }
+                }
+            }
+            for item in items {
+                rows.push(headers.iter().map(|h| json_to_sql(item.get(h))).collect());
+            }
+        } else {
+            let mut reader = csv::Reader::from_reader(text.as_bytes());
+            headers = reader
+                .headers()
+                .map_err(|e| e.to_string())?
+                .iter()
+                .map(|h| h.trim().to_string())
+                .collect();
+            for record in reader.records() {
+                let record = record.map_err(|e| format!("Bad CSV in '{}'. {}", file, e))?;
+                rows.push(headers.iter().enumerate().map(|(k, _)| csv_to_sql(record.get(k).unwrap_or(""))).collect());
+            }
+        }
+
+        let columns: Vec<String> = headers
+            .iter()
+            .map(|h| mapping.get(h).cloned().unwrap_or_else(|| h.clone()))
+            .collect();
+        if columns.is_empty() {
+            return Err(format!("'{}' has no columns.", file));
+        }
+
+        // Column types: INTEGER if every value is, REAL if every value is numeric, else TEXT
+        let types: Vec<&str> = (0..columns.len())
+            .map(|k| {
+                let present: Vec<&Value> = rows.iter().map(|r| &r[k]).filter(|v| **v != Value::Null).collect();
+                if !present.is_empty() && present.iter().all(|v| matches!(v, Value::Integer(_))) {
+                    "INTEGER"
+                } else if !present.is_empty() && present.iter().all(|v| matches!(v, Value::Integer(_) | Value::Real(_))) {
+                    "REAL"
+                } else {
+                    "TEXT"
+                }
+            })
+            .collect();
+
+        let in_transaction = self.sql_transactions.iter().any(|t| t == db);
+        let count = rows.len();
+        self.with_sql_connection(db, |conn| {
+            let exists: bool = conn.query_row(
+                "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                [table],
+                |r| r.get::<_, i64>(0),
+            )? > 0;
+
+            // Inside 'begin sql' the import joins the script's transaction
+            let tx = if in_transaction { None } else { Some(conn.unchecked_transaction()?) };
+            if !exists {
+                let defs: Vec<String> = columns
+                    .iter()
+                    .zip(&types)
+                    .map(|(c, t)| format!("{} {}", quote_ident(c), t))
+                    .collect();
+                conn.execute_batch(&format!("CREATE TABLE {} ({})", quote_ident(table), defs.join(", ")))?;
+            }
+            let insert = format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                quote_ident(table),
+                columns.iter().map(|c| quote_ident(c)).collect::<Vec<_>>().join(", "),
+                vec!["?"; columns.len()].join(", ")
+            );
+            {
+                let mut stmt = conn.prepare_cached(&insert)?;
+                for row in rows {
+                    stmt.execute(params_from_iter(row))?;
+                }
+            }
+            if let Some(tx) = tx {
+                tx.commit()?;
+            }
+            Ok(())
+        })
+        .map_err(|e| format!("Import of '{}' into '{}' failed, rolled back. {}", file, table, e))?;
+        Ok(count)
+    }
+
+    // Called when a bug stops the script: nothing half-written stays behind.
+    pub fn rollback_sql_transactions(&mut self) {
+        while let Some(name) = self.sql_transactions.pop() {
+            if self.with_sql_connection(&name, |conn| conn.execute_batch("ROLLBACK")).is_ok() {
+                println!("BigSQL: Rolled back the open transaction on '{}'.", name);
+            }
+        }
+    }
+
+    // Runs 'f' on the connection named 'target'. Paths are opened on first use and kept
+    // until they sit idle (see sweep_sql_connections) or 'close sql' closes them.
+    fn with_sql_connection<T>(
+        &self,
+        target: &str,
+        f: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, String> {
+        let conn = {
+            let mut map = self
+                .sql_connections
+                .lock()
+                .map_err(|_| String::from("SQL connections are unavailable"))?;
+            match map.get_mut(target) {
+                Some(entry) => {
+                    entry.last_used = Instant::now();
+                    entry.conn.clone()
+                }
+                None => {
+                    sweep_sql_connections(&mut map);
+                    let conn = Connection::open(target).map_err(|e| format!("Failed to open database. {}", e))?;
+                    let entry = SqlConnection::new(conn, false);
+                    let conn = entry.conn.clone();
+                    map.insert(target.to_string(), entry);
+                    conn
+                }
+            }
+        };
+        let conn = conn
+            .lock()
+            .map_err(|_| format!("SQL connection '{}' is unavailable", target))?;
+        f(&conn).map_err(|e| e.to_string())
+    }
+
+    // Inside a transaction a failed statement is a bug, so the transaction rolls back.
+    fn sql_failed(&mut self, target: &str, message: String) {
+        if self.sql_transactions.iter().any(|t| t == target) {
+            self.sql_bug(message);
+        } else {
+            println!("Big Error: {}", message);
+        }
+    }
+
+    // Reads the query and its optional 'with' values. Leaves 'i' on the last value.
+    // The values are bound to the '?' placeholders, never pasted into the SQL text.
+    fn read_sql_query(&mut self, i: &mut usize, tokens: &Vec<Token>) -> Option<(String, Vec<Value>)> {
+        let query_raw = self.get_token_value(&tokens[*i]);
+        let query = if self.sql_strict {
+            let is_literal = matches!(tokens[*i].token_type, TokenType::String(_));
+            if !is_literal || has_interpolation(&query_raw) {
+                self.sql_bug(format!(
+                    "Strict SQL only takes a literal query without $Variables. Bind values with '?' and 'with'. (Line {})",
+                    tokens[*i].line
+                ));
+                // Skip the rest of the statement so 'if any bug found' sees the bug
+                while *i + 1 < tokens.len() && tokens[*i + 1].line == tokens[*i].line {
+                    *i += 1;
+                }
+                return None;
+            }
+            query_raw.replace("\\$", "$")
+        } else {
+            self.interpolate_string(&query_raw)
+        };
+
+        let mut params = Vec::new();
+        if *i + 1 < tokens.len() && tokens[*i + 1].token_type == TokenType::With {
+            *i += 1;
+            let line = tokens[*i].line;
+            while *i + 1 < tokens.len() && tokens[*i + 1].token_type != TokenType::On {
+                // The values end with the line; without 'on' there is no database to run them on
+                if tokens[*i + 1].line != line || tokens[*i + 1].token_type == TokenType::EOF {
+                    self.sql_bug(format!("'with' values need 'on \"database\"' after them. (Line {})", line));
+                    return None;
+                }
+                *i += 1;
+                match tokens[*i].token_type {
+                    TokenType::Char(',') | TokenType::LBrace | TokenType::RBrace => {}
+                    TokenType::Identifier(ref name) => {
+                        params.push(sql_value(&self.get_variable(name).unwrap_or_default()));
+                    }
+                    _ => {
+                        let text = self.get_token_value(&tokens[*i]);
+                        params.push(sql_value(&self.interpolate_string(&text)));
                     }
                 }
             }
         }
+        Some((query, params))
     }
+
+    // Raises a catchable bug ('if any bug found')
+    fn sql_bug(&mut self, message: String) {
+        println!("Big Error: {}", message);
+        self.last_bug_found = true;
+        self.last_bug_type = format!("SQL Error: {}", message);
+        self.set_variable("BugType".to_string(), self.last_bug_type.clone());
+    }
+}
+
+// Closes idle path connections (run before a new one is opened). Named connections, busy ones
+// and ones inside a transaction (another worker's 'begin sql') are never closed here.
+fn sweep_sql_connections(map: &mut HashMap<String, SqlConnection>) {
+    let closable = |entry: &SqlConnection| {
+        !entry.named && entry.conn.try_lock().is_ok_and(|conn| conn.is_autocommit())
+    };
+    map.retain(|_, entry| !(closable(entry) && entry.last_used.elapsed() >= SQL_IDLE));
+
+    let mut paths: Vec<(Instant, String)> = map
+        .iter()
+        .filter(|(_, entry)| closable(entry))
+        .map(|(path, entry)| (entry.last_used, path.clone()))
+        .collect();
+    if paths.len() >= SQL_MAX_PATHS {
+        paths.sort();
+        for (_, path) in &paths[..=paths.len() - SQL_MAX_PATHS] {
+            map.remove(path);
+        }
+    }
+}
+
+// version -> (file name, applied at) from 'bigc_migrations'; empty when the table doesn't exist.
+fn applied_migrations(conn: &Connection) -> rusqlite::Result<HashMap<i64, (String, String)>> {
+    let tables: i64 = conn.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = 'bigc_migrations'",
+        [],
+        |r| r.get(0),
+    )?;
+    if tables == 0 {
+        return Ok(HashMap::new());
+    }
+    let mut stmt = conn.prepare("SELECT version, name, applied_at FROM bigc_migrations")?;
+    let rows = stmt.query_map([], |r| Ok((r.get::<_, i64>(0)?, (r.get(1)?, r.get(2)?))))?;
+    rows.collect()
+}
+
+// One row as {column: value}. NULL stays null, blobs become base64 text.
+fn row_to_json(row: &Row, columns: &[String]) -> serde_json::Value {
+    let mut map = serde_json::Map::new();
+    for (idx, name) in columns.iter().enumerate() {
+        let value = match row.get_ref(idx) {
+            Ok(ValueRef::Integer(n)) => serde_json::Value::from(n),
+            Ok(ValueRef::Real(f)) => serde_json::Value::from(f),
+            Ok(ValueRef::Text(t)) => serde_json::Value::String(String::from_utf8_lossy(t).to_string()),
+            Ok(ValueRef::Blob(b)) => serde_json::Value::String(general_purpose::STANDARD.encode(b)),
+            Ok(ValueRef::Null) | Err(_) => serde_json::Value::Null,
+        };
+        map.insert(name.clone(), value);
+    }
+    serde_json::Value::Object(map)
+}
+
+// "rows.csv" -> "csv", "rows.json" -> "json"
+fn file_format(file: &str) -> Result<&'static str, String> {
+    let lower = file.to_lowercase();
+    if lower.ends_with(".csv") {
+        Ok("csv")
+    } else if lower.ends_with(".json") {
+        Ok("json")
+    } else {
+        Err(format!("'{}' must end in .csv or .json.", file))
+    }
+}
+
+// CSV cells are untyped: empty is NULL, then INTEGER, REAL, or TEXT.
+// Leading zeros ("007") mark codes, not numbers, and stay TEXT.
+fn csv_to_sql(cell: &str) -> Value {
+    if cell.is_empty() {
+        return Value::Null;
+    }
+    let unsigned = cell.strip_prefix('-').unwrap_or(cell);
+    let leading_zero = unsigned.len() > 1 && unsigned.starts_with('0') && !unsigned.starts_with("0.");
+    match sql_value(cell) {
+        Value::Integer(n) => Value::Integer(n),
+        _ => match cell.parse::<f64>() {
+            Ok(f) if f.is_finite() && cell.trim() == cell && !leading_zero => Value::Real(f),
+            _ => Value::Text(cell.to_string()),
+        },
+    }
+}
+
+fn json_to_sql(value: Option<&serde_json::Value>) -> Value {
+    match value {
+        None | Some(serde_json::Value::Null) => Value::Null,
+        Some(serde_json::Value::Bool(b)) => Value::Integer(*b as i64),
+        Some(serde_json::Value::Number(n)) => match n.as_i64() {
+            Some(k) => Value::Integer(k),
+            None => Value::Real(n.as_f64().unwrap_or(0.0)),
+        },
+        Some(serde_json::Value::String(s)) => Value::Text(s.clone()),
+        Some(other) => Value::Text(other.to_string()), // Nested lists/maps stay JSON
+    }
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+// BigC values are text. Canonical integers ("42", not "042") bind as INTEGER so LIMIT and
+// comparisons against untyped columns behave; everything else binds as TEXT.
+fn sql_value(value: &str) -> Value {
+    match value.parse::<i64>() {
+        Ok(n) if n.to_string() == value => Value::Integer(n),
+        _ => Value::Text(value.to_string()),
+    }
+}
+
+// True when interpolate_string would substitute something ("$Name" or "${Name}", not "\$").
+fn has_interpolation(text: &str) -> bool {
+    let chars: Vec<char> = text.chars().collect();
+    (0..chars.len()).any(|k| {
+        chars[k] == '$'
+            && (k == 0 || chars[k - 1] != '\\')
+            && chars
+                .get(k + 1)
+                .is_some_and(|c| c.is_alphanumeric() || *c == '_' || *c == '{')
+    })
 }
@@ -20,7 +20,7 @@ impl Interpreter {
             special_cmd = s.clone();
         } else if start_type == TokenType::Get {
             if let TokenType::Identifier(ref s) = tokens[*i].token_type {
-                if s == "web" || s == "time" || s == "count" {
+                if s == "web" || s == "time" || s == "count" || s == "secret" {
                     special_cmd = s.clone();
                     *i += 1; // Consume the sub-command (web/time/count)
                 }
@@ -28,7 +28,7 @@ impl Interpreter {
         }
 
         if !special_cmd.is_empty()
-            && (special_cmd == "web" || special_cmd == "time" || special_cmd == "count") {
+            && (special_cmd == "web" || special_cmd == "time" || special_cmd == "count" || special_cmd == "secret") {
                 match special_cmd.as_str() {
                     "web" => {
                         if *i < tokens.len() {
@@ -66,6 +66,9 @@ impl Interpreter {
                     "count" => {
                         self.handle_get_count(i, tokens);
                     }
+                    "secret" => {
+                        self.handle_get_secret(i, tokens);
+                    }
                     _ => {}
                 }
                 // Check if we need to backup (if handle_set_as_multiple advanced too far? No, it handles it)
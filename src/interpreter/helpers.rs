@@ -43,6 +43,28 @@ impl Interpreter {
         true
     }
 
+    // '& set as {Rows}' and '& set as list {Rows}' both receive the same JSON text (e.g. a list of row maps).
+    pub fn set_json_result(&mut self, i: &mut usize, tokens: &Vec<Token>, result: String) {
+        let j = *i + 1;
+        if j + 3 < tokens.len()
+            && tokens[j].token_type == TokenType::Ampersand
+            && tokens[j + 1].token_type == TokenType::Set
+            && tokens[j + 2].token_type == TokenType::As
+            && tokens[j + 3].token_type == TokenType::List
+        {
+            let mut k = j + 4;
+            if k < tokens.len() {
+                let target = self.extract_braced_name(&mut k, tokens);
+                if !target.is_empty() {
+                    self.set_variable(target, result);
+                }
+            }
+            *i = k - 1;
+        } else {
+            self.handle_set_as_multiple(i, tokens, vec![result]);
+        }
+    }
+
     pub fn handle_set_as_multiple(
         &mut self,
         i: &mut usize,
@@ -518,6 +540,16 @@ impl Interpreter {
 
     pub fn parse_json_list(&self, raw: &str) -> Vec<String> {
         let trimmed = raw.trim();
+        // Real JSON first, so items may be maps (e.g. SQL rows) or contain commas
+        if let Ok(Value::Array(items)) = serde_json::from_str(trimmed) {
+            return items
+                .into_iter()
+                .map(|v| match v {
+                    Value::String(s) => s,
+                    other => other.to_string(),
+                })
+                .collect();
+        }
         if trimmed.starts_with('[') && trimmed.ends_with(']') {
             return trimmed[1..trimmed.len() - 1]
                 .split(',')
@@ -4,17 +4,17 @@ use crate::lexer::Lexer;
 use crate::luck::BigLuck;
 use crate::sound::SoundCommand;
 use crate::tokens::{Token, TokenType};
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs;
 use std::sync::mpsc::Sender;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Instant;
 
 mod actions;
 mod architect;
 pub mod biew;
 mod bigweb;
-mod bit;
+pub mod bit;
 mod bmath;
 mod books;
 mod control;
@@ -25,6 +25,7 @@ mod helpers;
 mod lists;
 mod maps;
 mod math_elements;
+mod secret;
 
 #[derive(Clone)]
 pub struct Interpreter {
@@ -65,6 +66,14 @@ pub struct Interpreter {
     pub luck: BigLuck,
 
     pub sql_enabled: bool,
+    pub sql_strict: bool, // 'use sql strict': queries must be literals, values bound with 'with'
+    pub sql_connections: Arc<Mutex<HashMap<String, dbr::SqlConnection>>>, // By name or path
+    pub sql_transactions: Vec<String>, // Connections with an open 'begin sql'
+    pub dbig_tables: Arc<Mutex<HashMap<String, dbig::DbigCached>>>, // Parsed .dbig files by path
+    pub dbig_batch: Option<(String, Vec<dbig::DbigOp>)>, // Open 'dbig begin' file and its queued writes
+    pub vault_path: String, // --vault, BIGC_VAULT, or secrets.bigvault
+    pub vault_keyfile: Option<String>, // --vault-key or BIGC_VAULT_KEYFILE
+    pub vault: Arc<RwLock<Option<BTreeMap<String, String>>>>, // Decrypted once 'get secret' opens it
     pub sbig_enabled: bool,
     pub pybig_enabled: bool,
     pub guy_enabled: bool,
@@ -76,12 +85,28 @@ pub struct Interpreter {
     pub return_triggered: bool,
 
     pub max_workers: usize,
-    pub rate_limit: usize,
+    pub rate_rules: Vec<bigweb::RateRule>,
+    pub rate_buckets: HashMap<String, bigweb::RateBucket>,
+    pub rate_pruned: Instant, // Last sweep of idle rate buckets
+    pub rate_key_doing: Option<String>,
     pub call_depth: usize,
     pub local_scopes: Vec<HashMap<String, String>>, 
     pub current_status: u16,
     pub current_headers: HashMap<String, String>,
     pub log_file: Option<String>,
+    pub log_format: String,
+    pub metrics_path: Option<String>,
+    pub openapi_path: Option<String>,
+    pub route_docs: HashMap<String, bigweb::RouteDoc>, // 'api' annotations by route key
+    pub last_route: Option<String>,                   // Route the next 'api' line describes
+    pub api_info: (String, String),                   // OpenAPI title + version
+    pub route_metrics: HashMap<String, bigweb::RouteMetrics>,
+    pub live_clients: Arc<Mutex<HashMap<String, bigweb::LiveClient>>>,
+    pub live_client: Option<String>, // Client served by the running live doing
+    pub max_body: usize,
+    pub upload_dir: Option<String>,
+    pub cors: Option<bigweb::CorsPolicy>,
+    pub sbig_simulate: bool, // 'start server' loads routes without binding
     pub ssl_config: Option<(String, String)>,
 
     pub start_time: Instant,
@@ -231,6 +256,14 @@ impl Interpreter {
             net: BigNet::new(),
             luck: BigLuck::new(),
             sql_enabled: false,
+            sql_strict: false,
+            sql_connections: Arc::new(Mutex::new(HashMap::new())),
+            sql_transactions: Vec::new(),
+            dbig_tables: Arc::new(Mutex::new(HashMap::new())),
+            dbig_batch: None,
+            vault_path: std::env::var("BIGC_VAULT").unwrap_or(String::from(secret::DEFAULT_VAULT)),
+            vault_keyfile: std::env::var("BIGC_VAULT_KEYFILE").ok(),
+            vault: Arc::new(RwLock::new(None)),
             sbig_enabled: false,
             pybig_enabled: false,
             guy_enabled: false,
@@ -239,12 +272,28 @@ impl Interpreter {
             last_bug_type: String::new(),
             return_triggered: false,
             max_workers: 1,
-            rate_limit: 0,
+            rate_rules: Vec::new(),
+            rate_buckets: HashMap::new(),
+            rate_pruned: Instant::now(),
+            rate_key_doing: None,
             call_depth: 0,
             local_scopes: Vec::new(),
             current_status: 200,
             current_headers: HashMap::new(),
             log_file: None,
+            log_format: String::from("plain"),
+            metrics_path: None,
+            openapi_path: None,
+            route_docs: HashMap::new(),
+            last_route: None,
+            api_info: (String::from("Sbig API"), String::from("1.0.0")),
+            route_metrics: HashMap::new(),
+            live_clients: Arc::new(Mutex::new(HashMap::new())),
+            live_client: None,
+            max_body: 10 * 1024 * 1024,
+            upload_dir: None,
+            cors: None,
+            sbig_simulate: false,
             ssl_config: None,
             start_time: Instant::now(),
             last_delta_tick: Instant::now(),
@@ -501,6 +550,40 @@ impl Interpreter {
                     self.handle_books(&mut i, &tokens);
                 }
 
+                // DBR (dbr.rs)
+                // Only words in front of 'sql', so they stay usable as names ({Commit})
+                TokenType::Identifier(w)
+                    if i + 1 < tokens.len()
+                        && tokens[i + 1].token_type == TokenType::Sql
+                        && ["connect", "begin", "commit", "rollback"].contains(&w.to_lowercase().as_str()) =>
+                {
+                    self.handle_sql_session(&mut i, &tokens);
+                }
+                TokenType::Identifier(w)
+                    if w.eq_ignore_ascii_case("migrate")
+                        && i + 1 < tokens.len()
+                        && tokens[i + 1].token_type == TokenType::Sql =>
+                {
+                    self.handle_migrate_sql(&mut i, &tokens);
+                }
+                TokenType::Export => {
+                    if i + 1 < tokens.len() && tokens[i + 1].token_type == TokenType::Sql {
+                        self.handle_sql_transfer(&mut i, &tokens);
+                    }
+                }
+                TokenType::Identifier(w)
+                    if w.eq_ignore_ascii_case("import")
+                        && i + 1 < tokens.len()
+                        && tokens[i + 1].token_type == TokenType::Sql =>
+                {
+                    self.handle_sql_transfer(&mut i, &tokens);
+                }
+                TokenType::Close => {
+                    if i + 1 < tokens.len() && tokens[i + 1].token_type == TokenType::Sql {
+                        self.handle_sql_session(&mut i, &tokens);
+                    }
+                }
+
                 // DBB (dbig.rs)
                 TokenType::Dbig => {
                     self.handle_dbig(&mut i, &tokens);
@@ -539,6 +622,21 @@ impl Interpreter {
                 TokenType::Reply => {
                     self.handle_reply(&mut i, &tokens);
                 }
+                // 'simulate' works the same way; 'simulate server' may come before 'use web'
+                TokenType::Identifier(w)
+                    if w.eq_ignore_ascii_case("simulate")
+                        && (self.sbig_enabled
+                            || (i + 1 < tokens.len() && tokens[i + 1].token_type == TokenType::Server)) =>
+                {
+                    self.handle_simulate(&mut i, &tokens);
+                }
+                // 'api' is only a command while the web module is on, so it stays usable as a name
+                TokenType::Identifier(w) if w.eq_ignore_ascii_case("api") && self.sbig_enabled => {
+                    self.handle_api(&mut i, &tokens);
+                }
+                TokenType::Push => {
+                    self.handle_push(&mut i, &tokens);
+                }
 
                 // Maps (maps.rs)
                 TokenType::Map => {
@@ -1021,7 +1119,11 @@ impl Interpreter {
         };
 
         let is_debug = self.get_variable("BigDebug").unwrap_or_default() == "true";
-        let old_val = self.get_variable(&name);
+        if is_debug {
+            // Secret values never reach the debug log
+            let old_val = self.get_variable(&name).map(|old| self.redact_secrets(&old));
+            BigDebug::log_var_change(&name, old_val.as_ref(), &self.redact_secrets(&value), is_debug);
+        }
 
         // Special System Variables or Global Suffixes (Always Global/Shared)
         if name == "ReturnValue"
@@ -1031,6 +1133,10 @@ impl Interpreter {
             || name == "RequestPath"
             || name == "RequestMethod"
             || name == "RequestExtra"
+            || name == "RequestClient"
+            || name == "RequestHeaders"
+            || name == "RequestData"
+            || name == "RequestFiles"
             || name.ends_with("Raw")
             || name.ends_with("Content")
             || name.ends_with("Layout")
@@ -1039,7 +1145,6 @@ impl Interpreter {
             || name == "BugType"
         {
             if let Ok(mut vars) = self.variables.write() {
-                BigDebug::log_var_change(&name, old_val.as_ref(), &value, is_debug);
                 vars.insert(name, value);
             }
             return;
@@ -1047,12 +1152,10 @@ impl Interpreter {
 
         // REVOLUTION RULE: If inside a Doing, EVERYTHING is Local (Shadowing)
         if let Some(scope) = self.local_scopes.last_mut() {
-            BigDebug::log_var_change(&name, old_val.as_ref(), &value, is_debug);
             scope.insert(name, value);
         } else {
             // Main script: Global (Shared)
             if let Ok(mut vars) = self.variables.write() {
-                BigDebug::log_var_change(&name, old_val.as_ref(), &value, is_debug);
                 vars.insert(name, value);
             }
         }
@@ -1060,7 +1163,7 @@ impl Interpreter {
 
     pub fn report_error(&mut self, message: &str, line: usize, col: usize) {
         println!("\n+--- BigC ERROR ---+");
-        println!("| Message:  {}", message);
+        println!("| Message:  {}", self.redact_secrets(message));
         println!("| Location: Line {}, Column {}", line, col);
 
         let lines: Vec<&str> = self.full_source.lines().collect();
@@ -1081,6 +1184,8 @@ impl Interpreter {
         }
         println!("+------------------+");
         self.last_error_pos = Some((line, col));
+        self.rollback_sql_transactions();
+        self.discard_dbig_batch();
     }
 
     pub fn handle_dot_assignment(
@@ -0,0 +1,175 @@
+use super::bit::{gcm_open, gcm_seal};
+use super::Interpreter;
+use crate::tokens::Token;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+
+// --- SECRETS VAULT ---
+// A vault file is one AES-GCM blob (see 'bit aes ... with') holding a JSON object of NAME -> value.
+// The master key is the bytes of a keyfile, or the BIGC_VAULT_PASS passphrase.
+pub const DEFAULT_VAULT: &str = "secrets.bigvault";
+const REDACTED: &str = "[REDACTED]";
+
+impl Interpreter {
+    // get secret "NAME" & set as {Key}
+    pub fn handle_get_secret(&mut self, i: &mut usize, tokens: &Vec<Token>) {
+        if *i >= tokens.len() {
+            return;
+        }
+        let raw = self.get_token_value(&tokens[*i]);
+        let name = self.interpolate_string(&raw);
+        match self.read_secret(&name) {
+            Ok(value) => self.handle_set_as_multiple(i, tokens, vec![value]),
+            Err(e) => {
+                // Skip the rest of the line, so 'if any bug found' on the next line can catch it
+                let line = tokens[*i].line;
+                while *i + 1 < tokens.len() && tokens[*i + 1].line == line {
+                    *i += 1;
+                }
+                println!("Big Error: {}", e);
+                self.last_bug_found = true;
+                self.last_bug_type = format!("Secret Error: {}", e);
+                self.set_variable("BugType".to_string(), self.last_bug_type.clone());
+            }
+        }
+    }
+
+    // Opens the vault on first use; later reads (and Sbig workers) share the decrypted copy.
+    fn read_secret(&mut self, name: &str) -> Result<String, String> {
+        let mut vault = self.vault.write().map_err(|_| String::from("Vault is unavailable."))?;
+        if vault.is_none() {
+            let master = vault_master(self.vault_keyfile.as_deref(), false)?;
+            *vault = Some(open_vault(&self.vault_path, &master)?);
+        }
+        vault
+            .as_ref()
+            .and_then(|secrets| secrets.get(name).cloned())
+            .ok_or(format!("No secret '{}' in '{}'.", name, self.vault_path))
+    }
+
+    // Hides every secret value the script has loaded. Used by --debug logs and report_error.
+    pub fn redact_secrets(&self, text: &str) -> String {
+        let Ok(vault) = self.vault.read() else {
+            return text.to_string();
+        };
+        let Some(secrets) = vault.as_ref() else {
+            return text.to_string();
+        };
+        let mut values: Vec<&String> = secrets.values().filter(|v| !v.is_empty()).collect();
+        // Longest first, so a secret that contains another is hidden whole
+        values.sort_by_key(|v| std::cmp::Reverse(v.len()));
+        let mut out = text.to_string();
+        for value in values {
+            if out.contains(value.as_str()) {
+                out = out.replace(value.as_str(), REDACTED);
+            }
+        }
+        out
+    }
+
+    // --- COMMAND LINE ---
+    // bigrun secret <vault> [list | add <NAME> [value] | remove <NAME>] [--key <keyfile>]
+    pub fn run_secret_cli(&mut self, filename: &str, args: &[String]) -> Result<(), String> {
+        let mut args = args.to_vec();
+        let mut keyfile = self.vault_keyfile.clone();
+        if let Some(p) = args.iter().position(|a| a == "--key") {
+            if p + 1 >= args.len() {
+                return Err(String::from("Usage: bigrun secret <vault> ... --key <keyfile>"));
+            }
+            keyfile = Some(args.remove(p + 1));
+            args.remove(p);
+        }
+        let command = args.first().map(|c| c.as_str()).unwrap_or("list");
+        let exists = std::path::Path::new(filename).exists();
+        if !exists && command != "add" {
+            return Err(format!("Could not find '{}'.", filename));
+        }
+
+        let master = vault_master(keyfile.as_deref(), true)?;
+        let mut secrets = if exists {
+            open_vault(filename, &master)?
+        } else {
+            if keyfile.is_none() && std::env::var("BIGC_VAULT_PASS").is_err() {
+                let again = rpassword::prompt_password("Repeat passphrase: ").map_err(|e| e.to_string())?;
+                if again.as_bytes() != master.as_slice() {
+                    return Err(String::from("Passphrases do not match."));
+                }
+            }
+            BTreeMap::new()
+        };
+
+        match command {
+            "list" => {
+                for name in secrets.keys() {
+                    println!("{}", name);
+                }
+            }
+            "add" => {
+                let name = args.get(1).ok_or("Usage: bigrun secret <vault> add <NAME> [value]")?;
+                // Leaving the value out prompts for it, so it stays out of the shell history
+                let value = match args.get(2) {
+                    Some(value) => value.to_string(),
+                    None => rpassword::prompt_password(format!("Value for {}: ", name)).map_err(|e| e.to_string())?,
+                };
+                let replaced = secrets.insert(name.to_string(), value).is_some();
+                save_vault(filename, &master, &secrets)?;
+                println!("Vault: {} '{}' in '{}'.", if replaced { "Updated" } else { "Added" }, name, filename);
+            }
+            "remove" => {
+                let name = args.get(1).ok_or("Usage: bigrun secret <vault> remove <NAME>")?;
+                if secrets.remove(name.as_str()).is_none() {
+                    return Err(format!("No secret '{}'.", name));
+                }
+                save_vault(filename, &master, &secrets)?;
+                println!("Vault: Removed '{}' from '{}'.", name, filename);
+            }
+            other => return Err(format!("Unknown secret command '{}'. Use list, add or remove.", other)),
+        }
+        Ok(())
+    }
+}
+
+// Keyfile bytes, else BIGC_VAULT_PASS, else (command line only) a hidden prompt.
+pub fn vault_master(keyfile: Option<&str>, prompt: bool) -> Result<Vec<u8>, String> {
+    if let Some(path) = keyfile {
+        return fs::read(path).map_err(|e| format!("Could not read vault key '{}'. {}", path, e));
+    }
+    if let Ok(pass) = std::env::var("BIGC_VAULT_PASS") {
+        return Ok(pass.into_bytes());
+    }
+    if prompt {
+        let pass = rpassword::prompt_password("Vault passphrase: ").map_err(|e| e.to_string())?;
+        return Ok(pass.into_bytes());
+    }
+    Err(String::from("No vault key. Set BIGC_VAULT_PASS or run with --vault-key <keyfile>."))
+}
+
+pub fn open_vault(path: &str, master: &[u8]) -> Result<BTreeMap<String, String>, String> {
+    let blob = fs::read_to_string(path).map_err(|e| format!("Could not read vault '{}'. {}", path, e))?;
+    let plain = gcm_open(&blob, master).map_err(|_| format!("Could not open vault '{}': wrong key or damaged file.", path))?;
+    serde_json::from_str(&plain).map_err(|_| format!("Vault '{}' is damaged.", path))
+}
+
+// Same tmp + rename dance as dbig_write, so a crash never leaves half a vault behind.
+pub fn save_vault(path: &str, master: &[u8], secrets: &BTreeMap<String, String>) -> Result<(), String> {
+    let plain = serde_json::to_string(secrets).map_err(|e| e.to_string())?;
+    let blob = gcm_seal(&plain, master)?;
+    let tmp_path = format!("{}.tmp", path);
+    let saved = fs::File::create(&tmp_path)
+        .and_then(|mut f| {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                f.set_permissions(fs::Permissions::from_mode(0o600))?;
+            }
+            f.write_all(blob.as_bytes())?;
+            f.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if let Err(e) = saved {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(format!("Could not save vault '{}'. {}", path, e));
+    }
+    Ok(())
+}
@@ -21,12 +21,31 @@ fn print_usage() {
     println!("Usage: bigrun <file.big> [args]");
     println!("       bigrun whatis <keyword>");
     println!("       bigrun show <file.big>");
-    println!("       bigrun pack <folder> <output.bigpak> [--key \"Secret\"]");
+    println!("       bigrun routes <file.big> [--openapi [out.json]]");
+    println!("       bigrun migrate <db> <folder> [--status | --dry-run]");
+    println!("       bigrun dbig <file.dbig> [keys | get <key> | set <key> <value>... | remove <key> | dump | import <data.json> | validate]");
+    println!("       bigrun secret <vault> [list | add <NAME> [value] | remove <NAME>] [--key <keyfile>]");
+    println!("       bigrun pack <folder> <output.bigpak> [--key \"Secret\"] [--sign <private.key>]");
+    println!("       bigrun <file.bigpak> [--verify <public.pub>]");
     println!("       bigrun bunpack <file.bigpak> [--key \"Secret\"]");
     println!("\nFlags:");
     println!("       -v, --version    Show engine version");
     println!("       -h, --help       Show this help message");
     println!("       --debug          Enable verbose trace and variable logging");
+    println!("       --vault <file>   Vault read by 'get secret' (default secrets.bigvault)");
+    println!("       --vault-key <f>  Keyfile that opens the vault (else BIGC_VAULT_PASS)");
+    println!("       --verify <pub>   Trusted key a .bigpak must be signed with (else BIGC_TRUSTED_KEY)");
+}
+
+// --vault / --vault-key choose the vault behind 'get secret'
+fn use_vault_flags(interpreter: &mut Interpreter, args: &[String]) {
+    let flag = |name: &str| args.iter().position(|a| a == name).and_then(|p| args.get(p + 1)).cloned();
+    if let Some(path) = flag("--vault") {
+        interpreter.vault_path = path;
+    }
+    if let Some(keyfile) = flag("--vault-key") {
+        interpreter.vault_keyfile = Some(keyfile);
+    }
 }
 
 fn main() {
@@ -50,15 +69,20 @@ fn main() {
 
     if command == "pack" {
         if args.len() < 4 {
-            println!("Usage: bigrun pack <folder> <output.bigpak> [--key \"Secret\"]");
+            println!("Usage: bigrun pack <folder> <output.bigpak> [--key \"Secret\"] [--sign <private.key>]");
             return;
         }
-        let key = if args.len() >= 6 && args[4] == "--key" {
-            Some(args[5].clone())
-        } else {
-            None
-        };
-        BigPack::pack(&args[2], &args[3], key);
+        let flag = |name: &str| args.iter().position(|a| a == name).and_then(|p| args.get(p + 1)).cloned();
+        BigPack::pack(&args[2], &args[3], flag("--key"));
+        if let Some(key_file) = flag("--sign") {
+            match BigPack::sign(&args[3], &key_file) {
+                Ok(sig_file) => println!("BigPack: Signed -> {}", sig_file),
+                Err(e) => {
+                    println!("BigPack Error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
         return;
     }
 
@@ -86,6 +110,89 @@ fn main() {
         return;
     }
 
+    if command == "routes" {
+        if args.len() < 3 {
+            println!("Usage: bigrun routes <file.big> [--openapi [out.json]]");
+            return;
+        }
+        let content = fs::read_to_string(&args[2]).unwrap_or_else(|_| {
+            println!("Big Error: Could not find or read file '{}'", args[2]);
+            std::process::exit(1);
+        });
+        let mut lexer = Lexer::new(&content);
+        let tokens = lexer.tokenize();
+
+        // Only the route lines are replayed; the rest of the script never runs
+        let mut interpreter = Interpreter::new();
+        interpreter.full_source = content.clone();
+        interpreter.sbig_simulate = true;
+        if interpreter.validate_syntax(&tokens) {
+            interpreter.collect_routes(&tokens, 0);
+        }
+
+        if args.len() >= 4 && args[3] == "--openapi" {
+            let spec = interpreter.render_openapi();
+            if args.len() >= 5 {
+                match fs::write(&args[4], spec) {
+                    Ok(_) => println!("BigWeb: OpenAPI written to '{}'", args[4]),
+                    Err(e) => println!("Big Error: Could not write '{}'. {}", args[4], e),
+                }
+            } else {
+                println!("{}", spec);
+            }
+        } else {
+            interpreter.print_routes();
+        }
+        return;
+    }
+
+    if command == "migrate" {
+        if args.len() < 4 {
+            println!("Usage: bigrun migrate <db> <folder> [--status | --dry-run]");
+            return;
+        }
+        let mode = if args.contains(&String::from("--status")) {
+            "status"
+        } else if args.contains(&String::from("--dry-run")) {
+            "dry"
+        } else {
+            "apply"
+        };
+        let mut interpreter = Interpreter::new();
+        interpreter.sql_enabled = true;
+        if let Err(e) = interpreter.run_migrations(&args[2], &args[3], mode) {
+            println!("Big Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if command == "dbig" {
+        if args.len() < 3 {
+            println!("Usage: bigrun dbig <file.dbig> [keys | get <key> | set <key> <value>... | remove <key> | dump | import <data.json> | validate]");
+            return;
+        }
+        let mut interpreter = Interpreter::new();
+        if let Err(e) = interpreter.run_dbig_cli(&args[2], &args[3..]) {
+            println!("Big Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if command == "secret" {
+        if args.len() < 3 {
+            println!("Usage: bigrun secret <vault> [list | add <NAME> [value] | remove <NAME>] [--key <keyfile>]");
+            return;
+        }
+        let mut interpreter = Interpreter::new();
+        if let Err(e) = interpreter.run_secret_cli(&args[2], &args[3..]) {
+            println!("Big Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let filename = command;
 
     if !filename.ends_with(".big")
@@ -98,8 +205,29 @@ fn main() {
     }
 
     if filename.ends_with(".bigpak") {
+        // Refuse to launch an archive that doesn't match its signature
+        let trusted_key = match args.iter().position(|a| a == "--verify") {
+            Some(p) => match args.get(p + 1) {
+                Some(public_key) => Some(public_key.clone()),
+                None => {
+                    println!("Usage: bigrun <file.bigpak> --verify <public.pub>");
+                    std::process::exit(1);
+                }
+            },
+            None => env::var("BIGC_TRUSTED_KEY").ok(),
+        };
+        match BigPack::check_launch(filename, trusted_key.as_deref()) {
+            Ok(true) => println!("BigPack: Signature verified."),
+            Ok(false) => {}
+            Err(e) => {
+                println!("BigPack Error: {}", e);
+                std::process::exit(1);
+            }
+        }
+
         let mut interpreter = Interpreter::new();
         interpreter.mounted_archive = Some(filename.to_string());
+        use_vault_flags(&mut interpreter, &args);
 
         let entry_points = vec!["app.big", "main.big", "main.guy"];
         let mut entry_content = None;
@@ -150,6 +278,7 @@ fn main() {
         interpreter.set_variable(String::from("BigDebug"), String::from("true"));
         println!("BigC: Debug Mode Enabled. Tracing execution...");
     }
+    use_vault_flags(&mut interpreter, &args);
 
     if !std::path::Path::new("env_lib").exists() {
         let _ = fs::create_dir("env_lib");